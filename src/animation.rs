@@ -1,11 +1,13 @@
+use atlas::{Atlas, AtlasRect};
+use rand::{thread_rng, Rng};
 use specs::{Component, VecStorage};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::OsStr;
 use std::fs;
 use std::fs::File;
 use std::path::PathBuf;
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 #[serde(deny_unknown_fields)]
 pub enum Framerate {
     /// Distance for one loop
@@ -14,20 +16,163 @@ pub enum Framerate {
     Fix(f32),
 }
 
-#[derive(Deserialize)]
+/// Maps are sorted so that the Krita import writes them in a stable order
+#[derive(Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct AnimationsConf {
-    pub table: HashMap<(AnimationSpecie, AnimationName), Vec<String>>,
-    pub parts: HashMap<String, AnimationPartConf>,
+    pub table: BTreeMap<(AnimationSpecie, AnimationName), Vec<String>>,
+    pub parts: BTreeMap<String, AnimationPartConf>,
     pub directory: PathBuf,
+    /// Directory where the packed atlas is cached, packed at each launch if none
+    #[serde(default)]
+    pub atlas_cache: Option<PathBuf>,
+    #[serde(default)]
+    pub state_machines: BTreeMap<AnimationSpecie, StateMachineConf>,
 }
 
-#[derive(Deserialize)]
+/// Direction the images of a specie are rotated to
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub enum Facing {
+    Body,
+    /// Fall back on the body if the entity has no aim
+    Aim,
+    /// Keep the last direction when not moving
+    Velocity,
+}
+
+impl Default for Facing {
+    fn default() -> Self {
+        Facing::Body
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+pub enum AnimationTrigger {
+    /// Life reaches 0, the animation is played after the entity is deleted
+    Death,
+    /// Life decreases
+    Hit,
+    /// Sword swings
+    SwordAttack,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TransitionConf {
+    pub trigger: AnimationTrigger,
+    /// Played once
+    pub animation: AnimationName,
+    /// Interrupts the current one-shot animation only if its priority is greater or equal
+    pub priority: u32,
+    /// Seconds the interrupted animation fades out over the new one,
+    /// also used when the one-shot animation ends
+    #[serde(default)]
+    pub blend: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct StateMachineConf {
+    #[serde(default)]
+    pub facing: Facing,
+    /// Velocity above which the Walk animation is looped instead of Idle
+    pub walk_threshold: f32,
+    /// Seconds Idle and Walk fade out when switching from one to the other
+    #[serde(default)]
+    pub walk_blend: f32,
+    #[serde(default)]
+    pub transitions: Vec<TransitionConf>,
+    /// Sound played on footstep events
+    #[serde(default)]
+    pub footstep_sound: Option<String>,
+}
+
+/// Transition with its animation loaded
+#[derive(Clone)]
+#[doc(hidden)]
+pub struct Transition {
+    pub trigger: AnimationTrigger,
+    pub animation: CompleteAnimation,
+    pub priority: u32,
+    pub blend: f32,
+}
+
+/// State machine with animations loaded
+#[derive(Clone)]
+#[doc(hidden)]
+pub struct StateMachine {
+    pub facing: Facing,
+    pub walk_threshold: f32,
+    pub walk_blend: f32,
+    pub walk_animation: Option<CompleteAnimation>,
+    pub transitions: Vec<Transition>,
+    pub footstep_sound: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct AnimationPartConf {
     pub filename: String,
     pub layer: f32,
     pub framerate: Framerate,
+    /// Events emitted when the animation reaches the frame
+    #[serde(default)]
+    pub events: Vec<(usize, AnimationEvent)>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub enum AnimationEvent {
+    PlaySound(String),
+    Hitbox(HitboxConf),
+    /// Insert the insertable of the map at the position of the entity
+    Spawn(String),
+    /// Play the footstep sound of the specie
+    Footstep,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+/// Tint and transform of the images of an entity
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AppearanceConf {
+    /// Palette of the configuration the tint is randomly picked in, white if none
+    #[serde(default)]
+    pub palette: Option<String>,
+    #[serde(default = "one")]
+    pub scale: f32,
+    /// Added to the facing angle
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "one")]
+    pub opacity: f32,
+}
+
+impl Default for AppearanceConf {
+    fn default() -> Self {
+        AppearanceConf {
+            palette: None,
+            scale: 1.0,
+            rotation: 0.0,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// Ball damaging the entities it touches
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HitboxConf {
+    pub radius: f32,
+    /// Distance of the center in the facing direction
+    pub distance: f32,
+    pub damage: usize,
+    pub groups: Vec<::entity::Group>,
 }
 
 lazy_static! {
@@ -37,10 +182,56 @@ lazy_static! {
 /// Animation parts must not be empty
 pub(crate) struct Animations {
     pub images: Vec<PathBuf>,
+    /// Images packed in pages, rects in the order of images
+    pub atlas: Atlas,
     table: HashMap<(AnimationSpecie, AnimationName), CompleteAnimation>,
+    state_machines: HashMap<AnimationSpecie, StateMachine>,
 }
 
 impl Animations {
+    /// Sounds played by animation events
+    pub fn sounds(&self) -> Vec<&String> {
+        let mut sounds = self.table
+            .values()
+            .flat_map(|animation| animation.parts.iter())
+            .flat_map(|part| part.events.iter())
+            .filter_map(|&(_, ref event)| match *event {
+                AnimationEvent::PlaySound(ref sound) => Some(sound),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        sounds.extend(
+            self.state_machines
+                .values()
+                .filter_map(|state_machine| state_machine.footstep_sound.as_ref()),
+        );
+        sounds
+    }
+
+    /// Check that all insertables spawned by animation events are declared
+    pub fn check_spawns(
+        &self,
+        insertables: &::resource::InsertablesMap,
+    ) -> Result<(), ::failure::Error> {
+        let spawns = self.table
+            .values()
+            .flat_map(|animation| animation.parts.iter())
+            .flat_map(|part| part.events.iter())
+            .filter_map(|&(_, ref event)| match *event {
+                AnimationEvent::Spawn(ref insertable) => Some(insertable),
+                _ => None,
+            });
+        for insertable in spawns {
+            if !insertables.contains_key(insertable) {
+                return Err(format_err!(
+                    "animation: spawned insertable \"{}\" is not declared",
+                    insertable
+                ));
+            }
+        }
+        Ok(())
+    }
+
     fn load() -> Result<Animations, ::failure::Error> {
         let animations_cfg: AnimationsConf =
             ::ron::de::from_reader(File::open("data/animation.ron")?)?;
@@ -79,7 +270,7 @@ impl Animations {
                         let len = stem.len();
                         let stem_string = stem.to_string_lossy();
                         let (name, _number) = stem_string.split_at(len - 4);
-                        name == part_name
+                        name == part.filename
                     } else {
                         false
                     }
@@ -89,30 +280,53 @@ impl Animations {
 
             if part_images.len() == 0 {
                 return Err(format_err!(
-                    "invalid animation configuration: \"{}\" have no images in \"{}\"",
+                    "invalid animation configuration: \"{}\" have no images \"{}\" in \"{}\"",
                     part_name,
+                    part.filename,
                     animations_cfg.directory.to_string_lossy()
                 ));
             }
 
             part_images.sort();
 
+            if let Some(&(frame, _)) = part.events
+                .iter()
+                .find(|&&(frame, _)| frame >= part_images.len())
+            {
+                return Err(format_err!(
+                    "invalid animation configuration: \"{}\" has an event on frame {} but {} images",
+                    part_name,
+                    frame,
+                    part_images.len()
+                ));
+            }
+
             parts_table.insert(
                 part_name,
                 AnimationPart {
                     framerate: part.framerate,
                     layer: part.layer,
+                    events: part.events.clone(),
                     images: part_images
                         .iter()
                         .enumerate()
                         .map(|(i, _)| i + images.len())
                         .collect(),
+                    rects: vec![],
                 },
             );
 
             images.append(&mut part_images);
         }
 
+        let atlas = Atlas::load(
+            &images,
+            animations_cfg.atlas_cache.as_ref().map(|p| p.as_path()),
+        )?;
+        for part in parts_table.values_mut() {
+            part.rects = part.images.iter().map(|&i| atlas.rects[i]).collect();
+        }
+
         let mut table = HashMap::new();
 
         for (&key, part_names) in &animations_cfg.table {
@@ -128,19 +342,58 @@ impl Animations {
             table.insert(key, complete_animation);
         }
 
-        Ok(Animations { images, table })
+        let mut state_machines = HashMap::new();
+        for (specie, conf) in animations_cfg.state_machines {
+            let mut transitions = vec![];
+            for transition in conf.transitions {
+                let animation = table
+                    .get(&(specie, transition.animation))
+                    .cloned()
+                    .ok_or(format_err!(
+                        "invalid animation configuration: state machine of {:?}: animation {:?} is not in the table",
+                        specie,
+                        transition.animation
+                    ))?;
+                transitions.push(Transition {
+                    trigger: transition.trigger,
+                    animation,
+                    priority: transition.priority,
+                    blend: transition.blend,
+                });
+            }
+            state_machines.insert(
+                specie,
+                StateMachine {
+                    facing: conf.facing,
+                    walk_threshold: conf.walk_threshold,
+                    walk_blend: conf.walk_blend,
+                    walk_animation: table.get(&(specie, AnimationName::Walk)).cloned(),
+                    transitions,
+                    footstep_sound: conf.footstep_sound,
+                },
+            );
+        }
+
+        Ok(Animations {
+            images,
+            atlas,
+            table,
+            state_machines,
+        })
     }
 }
 
-#[derive(Deserialize, Hash, PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 #[serde(deny_unknown_fields)]
 pub enum AnimationName {
     Idle,
     Walk,
     SwordAttack,
+    Flinch,
+    Death,
 }
 
-#[derive(Deserialize, Hash, PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 #[serde(deny_unknown_fields)]
 pub enum AnimationSpecie {
     Character,
@@ -154,6 +407,9 @@ pub struct AnimationPart {
     pub layer: f32,
     framerate: Framerate,
     images: Vec<usize>,
+    /// Location of the images in the atlas
+    rects: Vec<AtlasRect>,
+    events: Vec<(usize, AnimationEvent)>,
 }
 
 #[derive(Clone)]
@@ -176,36 +432,88 @@ impl CompleteAnimation {
 }
 
 impl AnimationPart {
-    pub fn image_at(&self, timer: f32, distance: f32) -> usize {
-        let len = self.images.len();
+    /// Number of frames elapsed, not wrapped
+    fn frame_count(&self, timer: f32, distance: f32) -> isize {
         match self.framerate {
-            Framerate::Walk(r) => {
-                let i = ((distance / r) * len as f32).floor() as usize;
-                self.images[i % len]
-            }
-            Framerate::Fix(r) => {
-                let i = (timer * r).floor() as usize;
-                self.images[i % len]
-            }
+            Framerate::Walk(r) => ((distance / r) * self.images.len() as f32).floor() as isize,
+            Framerate::Fix(r) => (timer * r).floor() as isize,
+        }
+    }
+
+    pub fn rect_at(&self, timer: f32, distance: f32) -> AtlasRect {
+        let i = self.frame_count(timer, distance).max(0) as usize;
+        self.rects[i % self.rects.len()]
+    }
+
+    /// Events of the frames reached after (from_timer, from_distance) until (timer, distance)
+    pub fn events_between(
+        &self,
+        (from_timer, from_distance): (f32, f32),
+        (timer, distance): (f32, f32),
+    ) -> Vec<&AnimationEvent> {
+        if self.events.is_empty() {
+            return vec![];
         }
+        let len = self.images.len() as isize;
+        let start = self.frame_count(from_timer, from_distance);
+        let end = self.frame_count(timer, distance);
+        // Wrapped frames are at most one loop
+        let start = start.max(end - len);
+        (start + 1..end + 1)
+            .flat_map(|frame| {
+                let frame = (frame % len + len) % len;
+                self.events
+                    .iter()
+                    .filter(move |&&(event_frame, _)| event_frame as isize == frame)
+                    .map(|&(_, ref event)| event)
+            })
+            .collect()
     }
 
     fn duration(&self) -> Option<f32> {
         match self.framerate {
             Framerate::Walk(_) => None,
-            Framerate::Fix(r) => Some(self.images.len() as f32 * r),
+            Framerate::Fix(r) => Some(self.images.len() as f32 / r),
         }
     }
 }
 
+/// Animation fading out over the current one
+#[derive(Clone)]
+#[doc(hidden)]
+pub struct Blend {
+    pub animation: CompleteAnimation,
+    pub timer: f32,
+    pub distance: f32,
+    pub remaining: f32,
+    pub duration: f32,
+}
+
 #[doc(hidden)]
 pub struct AnimationState {
     /// 0 is no walk
     pub distance: f32,
     pub specie: AnimationSpecie,
     pub idle_animation: CompleteAnimation,
-    pub animations: Vec<CompleteAnimation>,
+    pub state_machine: Option<StateMachine>,
+    /// Transition of the one-shot animation played over the loop
+    pub one_shot: Option<Transition>,
+    /// Walk is looped instead of Idle
+    pub walking: bool,
+    pub blend: Option<Blend>,
     pub timer: f32,
+    /// Angle of the images
+    pub facing: f32,
+    /// Values of the last update to detect triggers
+    pub last_life: Option<usize>,
+    pub last_sword_reloading: f32,
+    /// Multiplied with the images, alpha is the opacity
+    pub tint: [f32; 4],
+    pub scale: f32,
+    /// Added to the facing angle of the images
+    pub rotation: f32,
+    /// Remaining time of the white flash
+    pub flash: f32,
 }
 
 impl AnimationState {
@@ -214,12 +522,182 @@ impl AnimationState {
             distance: 0.0,
             specie,
             idle_animation: ANIMATIONS.table[&(specie, idle_animation)].clone(),
-            animations: vec![],
+            state_machine: ANIMATIONS.state_machines.get(&specie).cloned(),
+            one_shot: None,
+            walking: false,
+            blend: None,
             timer: 0.0,
+            facing: 0.0,
+            last_life: None,
+            last_sword_reloading: 0.0,
+            tint: [1.0; 4],
+            scale: 1.0,
+            rotation: 0.0,
+            flash: 0.0,
+        }
+    }
+
+    /// The color is picked in the palette of the configuration, white if the palette is
+    /// unknown or empty, palettes are checked at load
+    pub fn set_appearance(&mut self, appearance: &AppearanceConf, conf: &::resource::Conf) {
+        let color = appearance
+            .palette
+            .as_ref()
+            .and_then(|palette| conf.palettes.get(palette))
+            .and_then(|colors| thread_rng().choose(colors).cloned())
+            .unwrap_or((1.0, 1.0, 1.0));
+        self.tint = [color.0, color.1, color.2, appearance.opacity];
+        self.scale = appearance.scale;
+        self.rotation = appearance.rotation;
+    }
+
+    /// Play the animation of the transition unless a one-shot of greater priority plays
+    pub fn trigger(&mut self, trigger: AnimationTrigger) {
+        let transition = self.state_machine.as_ref().and_then(|state_machine| {
+            state_machine
+                .transitions
+                .iter()
+                .find(|transition| transition.trigger == trigger)
+                .cloned()
+        });
+        if let Some(transition) = transition {
+            let interrupt = self.one_shot
+                .as_ref()
+                .map(|one_shot| transition.priority >= one_shot.priority)
+                .unwrap_or(true);
+            if interrupt {
+                self.blend_out(transition.blend);
+                self.one_shot = Some(transition);
+                self.timer = 0.0;
+            }
+        }
+    }
+
+    /// Switch between the Idle and Walk loops depending on the velocity
+    pub fn update_walking(&mut self, velocity: f32) {
+        let (walking, walk_blend) = match self.state_machine {
+            Some(StateMachine {
+                walk_animation: Some(_),
+                walk_threshold,
+                walk_blend,
+                ..
+            }) => (velocity > walk_threshold, walk_blend),
+            _ => (false, 0.0),
+        };
+        if walking != self.walking {
+            if self.one_shot.is_none() {
+                self.blend_out(walk_blend);
+            }
+            self.walking = walking;
+        }
+    }
+
+    /// Remove the one-shot animation once finished
+    pub fn update_one_shot(&mut self) {
+        let finished = match self.one_shot {
+            Some(ref one_shot) if self.timer >= one_shot.animation.duration => {
+                Some((one_shot.animation.duration, one_shot.blend))
+            }
+            _ => None,
+        };
+        if let Some((duration, blend)) = finished {
+            self.blend_out(blend);
+            self.timer -= duration;
+            self.one_shot = None;
+        }
+    }
+
+    /// Fade out the current animation over the next one during the duration
+    fn blend_out(&mut self, duration: f32) {
+        if duration <= 0.0 {
+            return;
+        }
+        self.blend = Some(Blend {
+            animation: self.animation().clone(),
+            timer: self.timer,
+            distance: self.distance,
+            remaining: duration,
+            duration,
+        });
+    }
+
+    /// Whether the animation of the trigger deals damage itself
+    pub fn has_hitbox(&self, trigger: AnimationTrigger) -> bool {
+        self.state_machine
+            .as_ref()
+            .and_then(|state_machine| {
+                state_machine
+                    .transitions
+                    .iter()
+                    .find(|transition| transition.trigger == trigger)
+            })
+            .map(|transition| {
+                transition.animation.parts.iter().any(|part| {
+                    part.events.iter().any(|&(_, ref event)| match *event {
+                        AnimationEvent::Hitbox(_) => true,
+                        _ => false,
+                    })
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Animation played after the deletion of the entity if any
+    pub fn death_animation(&self) -> Option<CompleteAnimation> {
+        self.state_machine.as_ref().and_then(|state_machine| {
+            state_machine
+                .transitions
+                .iter()
+                .find(|transition| transition.trigger == AnimationTrigger::Death)
+                .map(|transition| transition.animation.clone())
+        })
+    }
+
+    /// Animation currently played
+    pub fn animation(&self) -> &CompleteAnimation {
+        if let Some(ref one_shot) = self.one_shot {
+            return &one_shot.animation;
+        }
+        match self.state_machine {
+            Some(StateMachine {
+                walk_animation: Some(ref walk_animation),
+                ..
+            }) if self.walking => walk_animation,
+            _ => &self.idle_animation,
         }
     }
 }
 
+/// Event emitted by the animation of an entity, or of a death animation
+#[doc(hidden)]
+pub struct EmittedEvent {
+    pub entity: Option<::specs::Entity>,
+    pub position: ::na::Isometry2<f32>,
+    pub event: AnimationEvent,
+}
+
+/// Events emitted this frame, processed by the animation event system
+#[derive(Deref, DerefMut)]
+#[doc(hidden)]
+pub struct AnimationEvents(pub Vec<EmittedEvent>);
+
+/// Death animation of a deleted entity
+#[doc(hidden)]
+pub struct DeathAnimation {
+    pub position: ::na::Isometry2<f32>,
+    pub animation: CompleteAnimation,
+    pub timer: f32,
+    pub tint: [f32; 4],
+    pub scale: f32,
+    /// Added to the facing angle of the images
+    pub rotation: f32,
+}
+
+/// Death animations played where their entities were deleted
+#[derive(Deref, DerefMut)]
+#[doc(hidden)]
+pub struct DeathAnimations(pub Vec<DeathAnimation>);
+
 impl Component for AnimationState {
     type Storage = VecStorage<Self>;
 }
@@ -230,7 +708,11 @@ pub struct AnimationImages(pub Vec<AnimationImage>);
 
 #[doc(hidden)]
 pub struct AnimationImage {
-    pub id: usize,
+    pub rect: AtlasRect,
     pub position: ::na::Isometry2<f32>,
     pub layer: f32,
+    pub tint: [f32; 4],
+    pub scale: f32,
+    /// From 0 for no flash to 1 for plain white
+    pub flash: f32,
 }
@@ -0,0 +1,292 @@
+use png::HasParameters;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Width and height of atlas pages, larger images get their own page
+const PAGE_SIZE: u32 = 2048;
+/// Transparent pixels between images so that linear filtering doesn't bleed
+const PADDING: u32 = 1;
+const CACHE_INDEX: &str = "atlas.ron";
+
+/// Location of an image in the atlas
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct AtlasRect {
+    pub page: usize,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    /// Size of the image in pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+/// RGBA pixels of a page
+pub struct AtlasPage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Images packed in pages, rects are in the order of the images
+pub struct Atlas {
+    pub pages: Vec<AtlasPage>,
+    pub rects: Vec<AtlasRect>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+struct CacheSource {
+    path: PathBuf,
+    len: u64,
+    modified: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheIndex {
+    sources: Vec<CacheSource>,
+    pages: Vec<(u32, u32)>,
+    rects: Vec<AtlasRect>,
+}
+
+struct Decoded {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+fn decode(path: &Path) -> Result<Decoded, ::failure::Error> {
+    let file =
+        File::open(path).map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+    let (info, mut reader) = ::png::Decoder::new(file)
+        .read_info()
+        .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+    if info.color_type != ::png::ColorType::RGBA || info.bit_depth != ::png::BitDepth::Eight {
+        return Err(format_err!(
+            "\"{}\": image must be 8 bits RGBA",
+            path.to_string_lossy()
+        ));
+    }
+    let mut pixels = vec![0; info.buffer_size()];
+    reader
+        .next_frame(&mut pixels)
+        .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+    Ok(Decoded {
+        width: info.width,
+        height: info.height,
+        pixels,
+    })
+}
+
+fn source(path: &Path) -> Result<CacheSource, ::failure::Error> {
+    let metadata =
+        fs::metadata(path).map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    Ok(CacheSource {
+        path: path.to_path_buf(),
+        len: metadata.len(),
+        modified,
+    })
+}
+
+/// Shelf being filled on a page
+struct Shelf {
+    y: u32,
+    height: u32,
+    x: u32,
+}
+
+struct PageBuilder {
+    page: AtlasPage,
+    shelves: Vec<Shelf>,
+}
+
+impl PageBuilder {
+    fn new(width: u32, height: u32) -> Self {
+        PageBuilder {
+            page: AtlasPage {
+                width,
+                height,
+                pixels: vec![0; (width * height * 4) as usize],
+            },
+            shelves: vec![],
+        }
+    }
+
+    /// Position of the image if it fits in the page
+    fn insert(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (page_width, page_height) = (self.page.width, self.page.height);
+        let width = width + PADDING;
+        let height = height + PADDING;
+        if let Some(shelf) = self.shelves
+            .iter_mut()
+            .find(|shelf| height <= shelf.height && shelf.x + width <= page_width)
+        {
+            let position = (shelf.x, shelf.y);
+            shelf.x += width;
+            return Some(position);
+        }
+        let y = self.shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0);
+        if y + height > page_height || width > page_width {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height,
+            x: width,
+        });
+        Some((0, y))
+    }
+
+    fn blit(&mut self, image: &Decoded, x: u32, y: u32) {
+        let row_len = (image.width * 4) as usize;
+        for row in 0..image.height {
+            let src = (row * image.width * 4) as usize;
+            let dst = (((y + row) * self.page.width + x) * 4) as usize;
+            self.page.pixels[dst..dst + row_len]
+                .copy_from_slice(&image.pixels[src..src + row_len]);
+        }
+    }
+}
+
+impl Atlas {
+    /// Pack the images, reuse the cache directory if it is up to date and write it otherwise
+    pub fn load(images: &[PathBuf], cache: Option<&Path>) -> Result<Atlas, ::failure::Error> {
+        let sources = images
+            .iter()
+            .map(|path| source(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some(cache) = cache {
+            if let Ok(atlas) = Self::load_cache(cache, &sources) {
+                return Ok(atlas);
+            }
+        }
+
+        let atlas = Self::pack(images)?;
+
+        if let Some(cache) = cache {
+            if let Err(e) = atlas.save_cache(cache, sources) {
+                eprintln!("Failed to write atlas cache: {}", e);
+            }
+        }
+        Ok(atlas)
+    }
+
+    /// Shelf packing of images sorted by height
+    pub fn pack(images: &[PathBuf]) -> Result<Atlas, ::failure::Error> {
+        let decoded = images
+            .iter()
+            .map(|path| decode(path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut order = (0..decoded.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| ::std::cmp::Reverse(decoded[i].height));
+
+        let mut builders: Vec<PageBuilder> = vec![];
+        let mut positions = vec![(0, 0, 0); decoded.len()];
+        for i in order {
+            let image = &decoded[i];
+            let inserted = builders
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(page, builder)| {
+                    builder
+                        .insert(image.width, image.height)
+                        .map(|(x, y)| (page, x, y))
+                })
+                .next();
+            let (page, x, y) = match inserted {
+                Some(inserted) => inserted,
+                None => {
+                    let mut builder = PageBuilder::new(
+                        PAGE_SIZE.max(image.width + PADDING),
+                        PAGE_SIZE.max(image.height + PADDING),
+                    );
+                    let (x, y) = builder
+                        .insert(image.width, image.height)
+                        .expect("page is large enough");
+                    builders.push(builder);
+                    (builders.len() - 1, x, y)
+                }
+            };
+            builders[page].blit(image, x, y);
+            positions[i] = (page, x, y);
+        }
+
+        let rects = decoded
+            .iter()
+            .zip(positions)
+            .map(|(image, (page, x, y))| {
+                let page_width = builders[page].page.width as f32;
+                let page_height = builders[page].page.height as f32;
+                AtlasRect {
+                    page,
+                    uv_min: [x as f32 / page_width, y as f32 / page_height],
+                    uv_max: [
+                        (x + image.width) as f32 / page_width,
+                        (y + image.height) as f32 / page_height,
+                    ],
+                    width: image.width,
+                    height: image.height,
+                }
+            })
+            .collect();
+
+        Ok(Atlas {
+            pages: builders.into_iter().map(|builder| builder.page).collect(),
+            rects,
+        })
+    }
+
+    fn load_cache(cache: &Path, sources: &[CacheSource]) -> Result<Atlas, ::failure::Error> {
+        let index: CacheIndex =
+            ::ron::de::from_reader(File::open(cache.join(CACHE_INDEX))?)?;
+        if index.sources != sources {
+            return Err(format_err!("atlas cache is outdated"));
+        }
+        let mut pages = vec![];
+        for (i, &(width, height)) in index.pages.iter().enumerate() {
+            let page = decode(&cache.join(format!("page_{}.png", i)))?;
+            if page.width != width || page.height != height {
+                return Err(format_err!("atlas cache page {} has invalid size", i));
+            }
+            pages.push(AtlasPage {
+                width,
+                height,
+                pixels: page.pixels,
+            });
+        }
+        Ok(Atlas {
+            pages,
+            rects: index.rects,
+        })
+    }
+
+    fn save_cache(&self, cache: &Path, sources: Vec<CacheSource>) -> Result<(), ::failure::Error> {
+        fs::create_dir_all(cache)?;
+        for (i, page) in self.pages.iter().enumerate() {
+            let file = File::create(cache.join(format!("page_{}.png", i)))?;
+            let mut encoder = ::png::Encoder::new(file, page.width, page.height);
+            encoder
+                .set(::png::ColorType::RGBA)
+                .set(::png::BitDepth::Eight);
+            encoder.write_header()?.write_image_data(&page.pixels)?;
+        }
+        let index = CacheIndex {
+            sources,
+            pages: self.pages
+                .iter()
+                .map(|page| (page.width, page.height))
+                .collect(),
+            rects: self.rects.clone(),
+        };
+        fs::write(cache.join(CACHE_INDEX), ::ron::ser::to_string(&index)?)?;
+        Ok(())
+    }
+}
@@ -1,73 +1,136 @@
+use std::collections::{HashMap, VecDeque};
 use std::fs::File;
 use std::io::Cursor;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use rand::distributions::{Distribution, Range};
+use rand::thread_rng;
 use rodio::decoder::Decoder;
 use rodio::Source;
+use ncollide2d::query::Ray;
+use ncollide2d::world::CollisionGroups;
 use show_message::UnwrapOrShow;
-use heck::SnakeCase;
-use enum_iterator::IntoEnumIterator;
 
-#[repr(C)]
-#[derive(Deserialize, Clone, Copy, Debug, IntoEnumIterator)]
-pub enum Sound {
-    BongoH,
-    BongoL,
-    Clave,
-    Conga,
-}
+use std::f32::consts::PI;
+
+/// Duration in seconds of the fade out of a stolen voice
+const STOLEN_VOICE_FADE: f32 = 0.005;
+
+/// Channels of the mixer
+const CHANNELS: u16 = 2;
+
+/// Sample rate used when the output device doesn't give its default format
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
 
-// 44100 Hz mono buffer
-struct SoundBuffer {
+/// Mono buffer at the sample rate of the mixer
+pub struct SoundBuffer {
     samples: Arc<Vec<i16>>,
+    sample_rate: u32,
+}
+
+/// Down-mix interleaved samples to mono by averaging channels
+fn down_mix(samples: Vec<i16>, channels: u16) -> Vec<i16> {
+    if channels <= 1 {
+        return samples;
+    }
+    samples
+        .chunks(channels as usize)
+        .map(|frame| (frame.iter().map(|&s| s as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect()
+}
+
+/// Linear interpolation of mono samples from one sample rate to another
+fn resample(samples: Vec<i16>, from: u32, to: u32) -> Vec<i16> {
+    if from == to || samples.is_empty() {
+        return samples;
+    }
+    let ratio = from as f64 / to as f64;
+    let len = (samples.len() as f64 / ratio).ceil() as usize;
+    (0..len)
+        .map(|i| {
+            let position = i as f64 * ratio;
+            let index = position as usize;
+            let fract = position - index as f64;
+            let sample = samples[index.min(samples.len() - 1)] as f64;
+            let next_sample = samples.get(index + 1).cloned().unwrap_or(0) as f64;
+            (sample + (next_sample - sample) * fract) as i16
+        })
+        .collect()
 }
 
 impl SoundBuffer {
-    fn new(sound: Decoder<Cursor<Vec<u8>>>) -> Result<Self, String> {
-        if sound.sample_rate() != 44100 {
-            return Err("invalid samples rate: must be 44100 Hz".into());
-        }
-        if sound.channels() != 1 {
-            return Err("invalid channels: must be mono".into());
+    /// Load any file supported by the decoder, down-mixed and resampled to sample_rate
+    pub fn load(path: &Path, sample_rate: u32) -> Result<Self, ::failure::Error> {
+        let mut buffer = vec![];
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut buffer))
+            .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+        let sound = Decoder::new(Cursor::new(buffer))
+            .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+        if sound.channels() == 0 || sound.sample_rate() == 0 {
+            return Err(format_err!("\"{}\": invalid sound: no channels or sample rate", path.to_string_lossy()));
         }
+        Ok(SoundBuffer::new(sound, sample_rate))
+    }
 
-        Ok(SoundBuffer {
-            samples: Arc::new(sound.collect::<Vec<_>>()),
-        })
+    fn new(sound: Decoder<Cursor<Vec<u8>>>, sample_rate: u32) -> Self {
+        let channels = sound.channels();
+        let sound_sample_rate = sound.sample_rate();
+        let samples = down_mix(sound.collect::<Vec<_>>(), channels);
+        SoundBuffer {
+            samples: Arc::new(resample(samples, sound_sample_rate, sample_rate)),
+            sample_rate,
+        }
     }
 
-    fn source(&self) -> SoundSource {
+    /// Source played speed times faster with linear interpolation
+    ///
+    /// The source fades out and ends when stolen is set
+    fn source(&self, volume: f32, speed: f32, stolen: Arc<AtomicBool>, cutoff: f32) -> SoundSource {
         SoundSource {
             samples: self.samples.clone(),
-            cursor: 0,
+            cursor: 0.0,
+            speed: speed as f64,
+            volume,
+            sample_rate: self.sample_rate,
+            stolen,
+            stolen_step: 1.0 / (STOLEN_VOICE_FADE * self.sample_rate as f32).max(1.0),
+            stolen_volume: 1.0,
+            low_pass: low_pass_coefficient(cutoff, self.sample_rate),
+            filtered: 0.0,
+            looping: false,
         }
     }
 
-    #[allow(unused)]
-    fn infinite_source(&self) -> InfiniteSoundSource {
+    /// Loop of len samples starting at cursor, padded with silence
+    fn infinite_source(&self, len: usize, cursor: usize) -> InfiniteSoundSource {
+        let len = len.max(1);
         InfiniteSoundSource {
             samples: self.samples.clone(),
-            cursor: 0,
-            len: self.samples.len(),
+            cursor: cursor % len,
+            len,
+            sample_rate: self.sample_rate,
         }
     }
 }
 
-// infinite sound soure from a 44100 Hz mono buffer
-#[allow(unused)]
+// infinite sound soure from a mono buffer
 struct InfiniteSoundSource {
     samples: Arc<Vec<i16>>,
     cursor: usize,
     len: usize,
+    sample_rate: u32,
 }
 
 impl Iterator for InfiniteSoundSource {
     type Item = i16;
     fn next(&mut self) -> Option<Self::Item> {
-        let sample = self.samples.get(self.cursor).cloned();
+        let sample = self.samples.get(self.cursor).cloned().unwrap_or(0);
         self.cursor = (self.cursor + 1) % self.len;
-        sample
+        Some(sample)
     }
 }
 
@@ -79,25 +142,114 @@ impl Source for InfiniteSoundSource {
         1
     }
     fn sample_rate(&self) -> u32 {
-        44100
+        self.sample_rate
     }
     fn total_duration(&self) -> Option<Duration> {
         None
     }
 }
 
-// sound soure from a 44100 Hz mono buffer
+/// Volume of a fading source set from the game
+pub struct FadingControl {
+    volume: AtomicUsize,
+    stop: AtomicBool,
+}
+
+impl FadingControl {
+    pub fn set_volume(&self, volume: f32) {
+        self.volume.store(volume.to_bits() as usize, Ordering::Relaxed);
+    }
+
+    /// Fade out and end the source
+    pub fn stop(&self) {
+        self.set_volume(0.0);
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// looping source from a mono buffer that fades to the volume of its control
+struct FadingSource {
+    source: InfiniteSoundSource,
+    control: Arc<FadingControl>,
+    volume: f32,
+    step: f32,
+}
+
+impl Iterator for FadingSource {
+    type Item = i16;
+    fn next(&mut self) -> Option<Self::Item> {
+        let target = f32::from_bits(self.control.volume.load(Ordering::Relaxed) as u32);
+        if self.volume < target {
+            self.volume = (self.volume + self.step).min(target);
+        } else {
+            self.volume = (self.volume - self.step).max(target);
+        }
+        if self.volume <= 0.0 && self.control.stop.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.source
+            .next()
+            .map(|sample| (sample as f32 * self.volume) as i16)
+    }
+}
+
+impl Source for FadingSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        1
+    }
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+// sound soure from a mono buffer
 struct SoundSource {
     samples: Arc<Vec<i16>>,
-    cursor: usize,
+    cursor: f64,
+    speed: f64,
+    volume: f32,
+    sample_rate: u32,
+    stolen: Arc<AtomicBool>,
+    stolen_step: f32,
+    stolen_volume: f32,
+    /// Coefficient of the one pole low-pass, 1 is no filtering
+    low_pass: f32,
+    filtered: f32,
+    looping: bool,
+}
+
+fn low_pass_coefficient(cutoff: f32, sample_rate: u32) -> f32 {
+    (1.0 - (-2.0 * PI * cutoff / sample_rate as f32).exp()).min(1.0).max(0.0)
 }
 
 impl Iterator for SoundSource {
     type Item = i16;
     fn next(&mut self) -> Option<Self::Item> {
-        let sample = self.samples.get(self.cursor).cloned();
-        self.cursor += 1;
-        sample
+        if self.stolen.load(Ordering::Relaxed) {
+            self.stolen_volume -= self.stolen_step;
+            if self.stolen_volume <= 0.0 {
+                return None;
+            }
+        }
+        let len = self.samples.len();
+        if self.looping && len > 0 {
+            self.cursor %= len as f64;
+        }
+        let index = self.cursor as usize;
+        let fract = (self.cursor - index as f64) as f32;
+        let sample = *self.samples.get(index)? as f32;
+        let next_index = if self.looping { (index + 1) % len } else { index + 1 };
+        let next_sample = self.samples.get(next_index).cloned().unwrap_or(0) as f32;
+        self.cursor += self.speed;
+        let sample = sample + (next_sample - sample) * fract;
+        self.filtered += self.low_pass * (sample - self.filtered);
+        Some((self.filtered * self.volume * self.stolen_volume) as i16)
     }
 }
 
@@ -109,68 +261,439 @@ impl Source for SoundSource {
         1
     }
     fn sample_rate(&self) -> u32 {
-        44100
+        self.sample_rate
     }
     fn total_duration(&self) -> Option<Duration> {
         None
     }
 }
 
-lazy_static! {
-    static ref SOUND_BUFFERS: Vec<SoundBuffer> = {
-        let sound_filenames = Sound::into_enum_iter()
-            .map(|variant| {
-                let name = format!("{:?}", variant);
-                format!("data/sounds/{}.ogg", name.to_snake_case())
-            })
-            .collect::<Vec<_>>();
+/// Each category is mixed in its own bus
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    Music,
+    Sfx,
+    Activator,
+    Ui,
+}
 
-        let mut sound_files = sound_filenames.iter()
-            .map(|s| {
-                let mut buffer = vec![];
-                let mut file = File::open(s)
-                    .unwrap_or_else_show(|e| format!("Failed to open sound {}: {}", s, e));
-                file.read_to_end(&mut buffer)
-                    .unwrap_or_else_show(|e| format!("Failed to read sound {}: {}", s, e));
-                Cursor::new(buffer)
-            })
-            .collect::<Vec<_>>();
+const NUMBER_OF_BUSES: usize = 4;
+
+impl Category {
+    fn bus(self) -> usize {
+        self as usize
+    }
+}
+
+fn default_volume() -> f32 {
+    1.0
+}
+
+fn default_max_voices() -> usize {
+    8
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SoundSettings {
+    /// Files played in turn
+    pub files: Vec<PathBuf>,
+    #[serde(default = "default_volume")]
+    pub volume: f32,
+    /// Volume is multiplied by a random factor in [1 - variation, 1 + variation]
+    #[serde(default)]
+    pub volume_variation: f32,
+    /// Pitch is shifted by a random number of semitones in [-variation, variation]
+    #[serde(default)]
+    pub pitch_variation: f32,
+    pub category: Category,
+    /// Maximal number of voices of the sound playing at the same time,
+    /// the oldest voice is stolen when a new one is played
+    #[serde(default = "default_max_voices")]
+    pub max_voices: usize,
+}
+
+struct BankSound {
+    variants: Vec<SoundBuffer>,
+    next_variant: AtomicUsize,
+    volume: f32,
+    volume_variation: f32,
+    pitch_variation: f32,
+    category: Category,
+    max_voices: usize,
+    /// Steal flags of the voices, a voice has ended when its source dropped the flag
+    voices: Mutex<VecDeque<Arc<AtomicBool>>>,
+}
+
+impl BankSound {
+    fn load(settings: &SoundSettings, sample_rate: u32) -> Result<Self, ::failure::Error> {
+        if settings.files.is_empty() {
+            return Err(format_err!("files must not be empty"));
+        }
+        if settings.max_voices == 0 {
+            return Err(format_err!("max_voices must not be 0"));
+        }
+        let variants = settings
+            .files
+            .iter()
+            .map(|file| SoundBuffer::load(file, sample_rate))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(BankSound {
+            variants,
+            next_variant: AtomicUsize::new(0),
+            volume: settings.volume,
+            volume_variation: settings.volume_variation.min(1.0).max(0.0),
+            pitch_variation: settings.pitch_variation.max(0.0),
+            category: settings.category,
+            max_voices: settings.max_voices,
+            voices: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    fn source(&self, cutoff: f32) -> SoundSource {
+        let stolen = Arc::new(AtomicBool::new(false));
+        {
+            let mut voices = self.voices.lock().unwrap();
+            voices.retain(|voice| Arc::strong_count(voice) > 1);
+            while voices.len() >= self.max_voices {
+                voices.pop_front().unwrap().store(true, Ordering::Relaxed);
+            }
+            voices.push_back(stolen.clone());
+        }
+
+        let variant = self.next_variant.fetch_add(1, Ordering::Relaxed) % self.variants.len();
+
+        let mut rng = thread_rng();
+        let mut volume = self.volume;
+        if self.volume_variation > 0.0 {
+            volume *= Range::new(1.0 - self.volume_variation, 1.0 + self.volume_variation)
+                .sample(&mut rng);
+        }
+        let mut speed = 1.0;
+        if self.pitch_variation > 0.0 {
+            let semitones = Range::new(-self.pitch_variation, self.pitch_variation).sample(&mut rng);
+            speed = 2f32.powf(semitones / 12.0);
+        }
+
+        self.variants[variant].source(volume, speed, stolen, cutoff)
+    }
+}
+
+/// Sounds declared in data/sounds.ron, referenced by name
+pub struct SoundBank {
+    sounds: HashMap<String, BankSound>,
+}
+
+impl SoundBank {
+    pub fn load<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Self, ::failure::Error> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+        let settings: HashMap<String, SoundSettings> = ::ron::de::from_reader(file)
+            .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+
+        let mut sounds = HashMap::new();
+        for (name, settings) in settings {
+            let sound = BankSound::load(&settings, sample_rate)
+                .map_err(|e| format_err!("sound \"{}\": {}", name, e))?;
+            sounds.insert(name, sound);
+        }
+        Ok(SoundBank { sounds })
+    }
+
+    /// Check that all sounds played by animation events are declared
+    pub fn check_animations(&self) -> Result<(), ::failure::Error> {
+        for sound in ::animation::ANIMATIONS.sounds() {
+            if !self.sounds.contains_key(sound) {
+                return Err(format_err!(
+                    "animation: sound \"{}\" is not declared in the sound bank",
+                    sound
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that all sounds referenced by the insertables are declared
+    pub fn check(&self, insertables: &::resource::InsertablesMap) -> Result<(), ::failure::Error> {
+        for (name, insertable) in insertables {
+            for component in insertable.components() {
+                let sounds = match *component {
+                    ::entity::MetaComponent::Activators(ref activators) => {
+                        activators.iter().map(|activator| &activator.sound).collect::<Vec<_>>()
+                    }
+                    ::entity::MetaComponent::SoundEmitter(ref emitter) => vec![&emitter.sound],
+                    _ => vec![],
+                };
+                for sound in sounds {
+                    if !self.sounds.contains_key(sound) {
+                        return Err(format_err!(
+                            "insertable \"{}\": sound \"{}\" is not declared in the sound bank",
+                            name,
+                            sound
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maximal difference in seconds between the clock estimation and the mixer clock
+const MAX_CLOCK_DRIFT: f64 = 0.1;
+/// Part of the drift corrected on each update
+const CLOCK_CORRECTION: f64 = 0.01;
+
+/// Write 16 bits PCM samples in a WAV file
+pub fn write_wav(path: &Path, channels: u16, sample_rate: u32, samples: &[i16]) -> Result<(), ::failure::Error> {
+    fn u16_le(value: u16) -> [u8; 2] {
+        [value as u8, (value >> 8) as u8]
+    }
+    fn u32_le(value: u32) -> [u8; 4] {
+        [value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]
+    }
+
+    let data_len = samples.len() as u32 * 2;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&u32_le(36 + data_len));
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&u32_le(16));
+    // PCM
+    bytes.extend_from_slice(&u16_le(1));
+    bytes.extend_from_slice(&u16_le(channels));
+    bytes.extend_from_slice(&u32_le(sample_rate));
+    bytes.extend_from_slice(&u32_le(sample_rate * channels as u32 * 2));
+    bytes.extend_from_slice(&u16_le(channels * 2));
+    bytes.extend_from_slice(&u16_le(16));
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&u32_le(data_len));
+    for &sample in samples {
+        bytes.extend_from_slice(&u16_le(sample as u16));
+    }
+
+    File::create(path)
+        .and_then(|mut file| file.write_all(&bytes))
+        .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))
+}
+
+enum Output {
+    /// The mixer is pulled by the output device
+    Device,
+    /// The mixer is pulled on each update following the simulation time,
+    /// samples are kept if recorded
+    Offline {
+        mixer: Mutex<::dynamic_mixer::DynamicMixer<i16>>,
+        recorded: Option<Vec<i16>>,
+    },
+}
+
+/// Spatialization of an emitter source set from the game, values are f32 bits
+pub struct EmitterControl {
+    left_volume: AtomicUsize,
+    right_volume: AtomicUsize,
+    low_pass: AtomicUsize,
+    doppler: AtomicUsize,
+}
+
+fn store_f32(atomic: &AtomicUsize, value: f32) {
+    atomic.store(value.to_bits() as usize, Ordering::Relaxed);
+}
 
-        let mut sound_buffers = vec![];
-        for (file, filename) in sound_files.drain(..).zip(sound_filenames.iter()) {
-            let sound = Decoder::new(file)
-                .unwrap_or_else_show(|e| format!("Failed to decode sound {}: {}", filename, e));
+fn load_f32(atomic: &AtomicUsize) -> f32 {
+    f32::from_bits(atomic.load(Ordering::Relaxed) as u32)
+}
 
-            let sound = SoundBuffer::new(sound)
-                .unwrap_or_else_show(|e| format!("Invalid sound: {}: {}", filename, e));
+/// Part of the distance to the spatialization of the control covered on each frame
+const EMITTER_SMOOTHING: f32 = 0.002;
 
-            sound_buffers.push(sound);
+// stereo source following its control, ends when the control is dropped by the game
+struct EmitterSource {
+    source: SoundSource,
+    control: Arc<EmitterControl>,
+    speed: f64,
+    left_volume: f32,
+    right_volume: f32,
+    sample: f32,
+    right_channel: bool,
+}
+
+impl Iterator for EmitterSource {
+    type Item = i16;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.right_channel {
+            self.right_channel = false;
+            return Some((self.sample * self.right_volume) as i16);
+        }
+
+        if Arc::strong_count(&self.control) == 1 {
+            self.source.stolen.store(true, Ordering::Relaxed);
         }
-        sound_buffers
-    };
+        let smooth = |value: &mut f32, target: f32| *value += (target - *value) * EMITTER_SMOOTHING;
+        smooth(&mut self.left_volume, load_f32(&self.control.left_volume));
+        smooth(&mut self.right_volume, load_f32(&self.control.right_volume));
+        self.source.low_pass = load_f32(&self.control.low_pass);
+        self.source.speed = self.speed * load_f32(&self.control.doppler) as f64;
+
+        self.sample = self.source.next()? as f32;
+        self.right_channel = true;
+        Some((self.sample * self.left_volume) as i16)
+    }
+}
+
+impl Source for EmitterSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        2
+    }
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Attenuation of the sounds behind walls
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OcclusionSettings {
+    /// Volume is multiplied by this factor for each wall between the source and the listener
+    pub volume: f32,
+    /// Cutoff frequency in Hz of the low-pass of occluded sounds
+    pub cutoff: f32,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub enum Reverb {
+    Room,
+    Hall,
+    Cave,
+    Custom(::dynamic_mixer::ReverbSettings),
+}
+
+impl Reverb {
+    fn settings(&self) -> ::dynamic_mixer::ReverbSettings {
+        use dynamic_mixer::ReverbSettings;
+        match *self {
+            Reverb::Room => ReverbSettings {
+                room_size: 0.7,
+                damping: 0.5,
+                wet: 0.2,
+            },
+            Reverb::Hall => ReverbSettings {
+                room_size: 0.85,
+                damping: 0.3,
+                wet: 0.3,
+            },
+            Reverb::Cave => ReverbSettings {
+                room_size: 0.93,
+                damping: 0.1,
+                wet: 0.4,
+            },
+            Reverb::Custom(ref settings) => settings.clone(),
+        }
+    }
 }
 
 pub struct Audio {
+    bank: SoundBank,
     mixer: Arc<::dynamic_mixer::DynamicMixerController<i16>>,
+    output: Output,
 
+    /// Position of the listener
     position: ::na::Vector2<f32>,
+    velocity: ::na::Vector2<f32>,
 
     unit: f32,
     clamp: ::util::ClampFunction,
+    /// Cutoff frequency of the low-pass depending on the distance
+    distance_cutoff: ::util::ClampFunction,
+    occlusion: Option<OcclusionSettings>,
+    /// Speed of sound for Doppler effect in world unit per second
+    sound_speed: f32,
+
+    /// Delay of scheduled sounds so they are never scheduled in the past
+    latency: f32,
+    /// Estimation of the mixer clock at the current update
+    clock: f64,
+    /// Sample rate of the output device
+    sample_rate: u32,
 }
 
 impl Audio {
+    /// Play on the default output device, or render offline without recording
+    /// if there is no device
     pub fn init(conf: &::resource::Conf, save: &::resource::Save) -> Self {
-        let device = ::rodio::default_output_device().unwrap();
+        let device = match ::rodio::default_output_device() {
+            Some(device) => device,
+            None => return Audio::init_offline(conf, save, DEFAULT_SAMPLE_RATE, false),
+        };
+        // The mixer runs at the rate of the device so that the sink doesn't convert it
+        let sample_rate = device
+            .default_output_format()
+            .map(|format| format.sample_rate.0)
+            .unwrap_or(DEFAULT_SAMPLE_RATE);
 
-        let (mixer_ctrl, mixer) = ::dynamic_mixer::mixer(2, 44100);
+        let (audio, mixer) = Audio::new(conf, save, sample_rate);
 
         let sink = ::rodio::Sink::new(&device);
-        sink.append(mixer.mix(::rodio::source::Zero::<i16>::new(2, 44100)));
+        sink.append(mixer.mix(::rodio::source::Zero::<i16>::new(CHANNELS, sample_rate)));
         sink.detach();
 
-        Audio {
+        audio
+    }
+
+    /// Render the audio on each update instead of playing it on a device
+    ///
+    /// If record is set then the samples are kept, see `recorded` and `write_wav`
+    pub fn init_offline(
+        conf: &::resource::Conf,
+        save: &::resource::Save,
+        sample_rate: u32,
+        record: bool,
+    ) -> Self {
+        let (mut audio, mixer) = Audio::new(conf, save, sample_rate);
+        audio.output = Output::Offline {
+            mixer: Mutex::new(mixer),
+            recorded: if record { Some(vec![]) } else { None },
+        };
+        audio
+    }
+
+    fn new(
+        conf: &::resource::Conf,
+        save: &::resource::Save,
+        sample_rate: u32,
+    ) -> (Self, ::dynamic_mixer::DynamicMixer<i16>) {
+        let bank = SoundBank::load("data/sounds.ron", sample_rate)
+            .unwrap_or_else_show(|e| format!("Failed to load sound bank: {}", e));
+        bank.check(&conf.insertables)
+            .unwrap_or_else_show(|e| format!("Invalid configuration: {}", e));
+        bank.check_animations()
+            .unwrap_or_else_show(|e| format!("Invalid animation configuration: {}", e));
+
+        let (mixer_ctrl, mixer) = ::dynamic_mixer::mixer(
+            CHANNELS,
+            sample_rate,
+            NUMBER_OF_BUSES,
+            Some((
+                Category::Music.bus(),
+                vec![Category::Sfx.bus()],
+                conf.audio_ducking.clone(),
+            )),
+            conf.audio_limiter.clone(),
+            vec![Category::Sfx.bus(), Category::Activator.bus()],
+        );
+
+        let audio = Audio {
+            bank,
             position: ::na::Vector2::new(0.0, 0.0),
+            velocity: ::na::Vector2::new(0.0, 0.0),
             unit: conf.audio_unit,
             clamp: ::util::ClampFunction {
                 min_t: conf.audio_clamp_start,
@@ -178,27 +701,406 @@ impl Audio {
                 min_value: save.audio_volume,
                 max_value: 0.0,
             },
+            distance_cutoff: conf.audio_distance_cutoff.clone(),
+            occlusion: conf.audio_occlusion.clone(),
+            sound_speed: conf.audio_sound_speed,
             mixer: mixer_ctrl,
+            output: Output::Device,
+            latency: conf.audio_latency,
+            clock: 0.0,
+            sample_rate,
+        };
+        (audio, mixer)
+    }
+
+    /// Interleaved stereo samples rendered since the offline initialization
+    pub fn recorded(&self) -> Option<&[i16]> {
+        match self.output {
+            Output::Offline {
+                recorded: Some(ref recorded),
+                ..
+            } => Some(recorded),
+            _ => None,
         }
     }
 
-    pub fn play(&self, sound: Sound, position: ::na::Vector2<f32>) {
-        let position = position * self.unit;
-        let distance = position - self.position;
-        let volume = self.clamp.compute(distance.norm());
+    /// Write the recorded samples in a WAV file
+    pub fn write_wav(&self, path: &Path) -> Result<(), ::failure::Error> {
+        let recorded = self.recorded()
+            .ok_or(format_err!("audio is not recorded"))?;
+        write_wav(path, CHANNELS, self.sample_rate, recorded)
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn sound_bank(&self) -> &SoundBank {
+        &self.bank
+    }
+
+    /// Bus and source, None if the sound is not declared in the sound bank
+    fn spatialized_source(
+        &self,
+        sound: &str,
+        position: ::na::Vector2<f32>,
+        physic_world: &::resource::PhysicWorld,
+    ) -> Option<(usize, ::rodio::source::ChannelVolume<SoundSource>)> {
+        let sound = self.bank.sounds.get(sound)?;
+        let (left_volume, right_volume, cutoff) = self.spatialization(position, physic_world);
+        Some((sound.category.bus(), ::rodio::source::ChannelVolume::new(sound.source(cutoff), vec![left_volume, right_volume])))
+    }
+
+    /// Left volume, right volume and low-pass cutoff of a sound at the position
+    fn spatialization(
+        &self,
+        position: ::na::Vector2<f32>,
+        physic_world: &::resource::PhysicWorld,
+    ) -> (f32, f32, f32) {
+        let distance = (position - self.position) * self.unit;
+        let mut volume = self.clamp.compute(distance.norm());
+        let mut cutoff = self.distance_cutoff.compute(distance.norm());
+        if let Some(ref occlusion) = self.occlusion {
+            let walls = self.walls_to_listener(position, physic_world);
+            if walls > 0 {
+                volume *= occlusion.volume.powi(walls as i32);
+                cutoff = cutoff.min(occlusion.cutoff);
+            }
+        }
         let pan = ((distance[0].min(1.0).max(-1.0)+1.0)/2.0).min(1.0).max(0.0);
 
         let left_volume = (1.0-pan).sqrt()*volume;
         let right_volume = pan.sqrt()*volume;
 
-        let source = ::rodio::source::ChannelVolume::new(SOUND_BUFFERS[sound as usize].source(), vec![left_volume, right_volume]);
-        self.mixer.add(source);
+        (left_volume, right_volume, cutoff)
+    }
+
+    /// Pitch factor of a sound emitted at the position with the velocity
+    fn doppler(&self, position: ::na::Vector2<f32>, velocity: ::na::Vector2<f32>) -> f32 {
+        let to_listener = self.position - position;
+        let distance = to_listener.norm();
+        if distance == 0.0 {
+            return 1.0;
+        }
+        let direction = to_listener / distance;
+        let source_speed = velocity.dot(&direction).min(self.sound_speed * 0.9);
+        let listener_speed = -self.velocity.dot(&direction);
+        ((self.sound_speed + listener_speed) / (self.sound_speed - source_speed))
+            .min(2.0)
+            .max(0.5)
+    }
+
+    /// Play the sound following an emitter until the control is dropped
+    ///
+    /// Doppler effect is computed if the velocity is given
+    pub fn play_emitter(
+        &self,
+        sound: &str,
+        looping: bool,
+        position: ::na::Vector2<f32>,
+        velocity: Option<::na::Vector2<f32>>,
+        physic_world: &::resource::PhysicWorld,
+    ) -> Option<Arc<EmitterControl>> {
+        let sound = self.bank.sounds.get(sound)?;
+        let control = Arc::new(EmitterControl {
+            left_volume: AtomicUsize::new(0),
+            right_volume: AtomicUsize::new(0),
+            low_pass: AtomicUsize::new(0),
+            doppler: AtomicUsize::new(0),
+        });
+        self.update_emitter(&control, position, velocity, physic_world);
+
+        let mut source = sound.source(::std::f32::INFINITY);
+        source.looping = looping;
+        let source = EmitterSource {
+            speed: source.speed,
+            source,
+            left_volume: load_f32(&control.left_volume),
+            right_volume: load_f32(&control.right_volume),
+            control: control.clone(),
+            sample: 0.0,
+            right_channel: false,
+        };
+        self.mixer.add(source, sound.category.bus());
+        Some(control)
+    }
+
+    pub fn update_emitter(
+        &self,
+        control: &EmitterControl,
+        position: ::na::Vector2<f32>,
+        velocity: Option<::na::Vector2<f32>>,
+        physic_world: &::resource::PhysicWorld,
+    ) {
+        let (left_volume, right_volume, cutoff) = self.spatialization(position, physic_world);
+        store_f32(&control.left_volume, left_volume);
+        store_f32(&control.right_volume, right_volume);
+        store_f32(&control.low_pass, low_pass_coefficient(cutoff, self.sample_rate));
+        store_f32(
+            &control.doppler,
+            velocity.map(|velocity| self.doppler(position, velocity)).unwrap_or(1.0),
+        );
+    }
+
+    /// Number of walls crossed by the segment from the position to the listener
+    fn walls_to_listener(&self, position: ::na::Vector2<f32>, physic_world: &::resource::PhysicWorld) -> usize {
+        let ray = Ray::new(::na::Point::from_coordinates(position), self.position - position);
+        let mut collision_groups = CollisionGroups::new();
+        collision_groups.set_whitelist(&[::entity::Group::Wall as usize]);
+        physic_world
+            .collision_world()
+            .interferences_with_ray(&ray, &collision_groups)
+            .filter(|(_, intersection)| intersection.toi < 1.0)
+            .count()
+    }
+
+    /// Frame of the mixer clock at the time relative to the current update
+    fn frame_at(&self, time: f32) -> usize {
+        (self.clock + ((time + self.latency) * self.sample_rate as f32) as f64).max(0.0) as usize
+    }
+
+    pub fn play(&self, sound: &str, position: ::na::Vector2<f32>, physic_world: &::resource::PhysicWorld) {
+        if let Some((bus, source)) = self.spatialized_source(sound, position, physic_world) {
+            self.mixer.add(source, bus);
+        }
+    }
+
+    /// Play the sound at the time relative to the current update, negative for past
+    ///
+    /// All scheduled sounds are delayed by the audio latency
+    pub fn play_at(
+        &self,
+        sound: &str,
+        position: ::na::Vector2<f32>,
+        time: f32,
+        physic_world: &::resource::PhysicWorld,
+    ) {
+        if let Some((bus, source)) = self.spatialized_source(sound, position, physic_world) {
+            self.mixer.add_at(source, bus, self.frame_at(time));
+        }
+    }
+
+    /// Advance the estimation of the mixer clock, must be called on each update
+    pub fn advance_clock(&mut self, update_time: f32) {
+        self.clock += update_time as f64 * self.sample_rate as f64;
+        if let Output::Offline {
+            ref mut mixer,
+            ref mut recorded,
+        } = self.output
+        {
+            let mixer = mixer.get_mut().unwrap();
+            while (self.mixer.clock() as f64) < self.clock {
+                for _ in 0..CHANNELS {
+                    let sample = mixer.next().unwrap();
+                    if let Some(ref mut recorded) = *recorded {
+                        recorded.push(sample);
+                    }
+                }
+            }
+        }
+        let drift = self.mixer.clock() as f64 - self.clock;
+        if drift.abs() > MAX_CLOCK_DRIFT * self.sample_rate as f64 {
+            self.clock += drift;
+        } else {
+            self.clock += drift * CLOCK_CORRECTION;
+        }
+    }
+
+    /// Play the buffer in loop of len samples from cursor, centered, in the music bus,
+    /// at the time relative to the current update like `play_at`
+    ///
+    /// The source fades in from silence to the volume of the control in fade seconds
+    pub fn play_loop(
+        &self,
+        buffer: &SoundBuffer,
+        len: usize,
+        cursor: usize,
+        volume: f32,
+        fade: f32,
+        time: f32,
+    ) -> Arc<FadingControl> {
+        let control = Arc::new(FadingControl {
+            volume: AtomicUsize::new(0),
+            stop: AtomicBool::new(false),
+        });
+        control.set_volume(volume);
+
+        let source = FadingSource {
+            source: buffer.infinite_source(len, cursor),
+            control: control.clone(),
+            volume: 0.0,
+            step: 1.0 / (fade * self.sample_rate as f32).max(1.0),
+        };
+        self.mixer.add_at(
+            ::rodio::source::ChannelVolume::new(source, vec![1.0, 1.0]),
+            Category::Music.bus(),
+            self.frame_at(time),
+        );
+        control
     }
 
-    pub fn update(&mut self, position: Option<::na::Vector2<f32>>, save: &::resource::Save) {
+    /// Listener is the position and velocity of the player if any
+    pub fn update(&mut self, listener: Option<(::na::Vector2<f32>, ::na::Vector2<f32>)>, save: &::resource::Save) {
         self.clamp.min_value = save.audio_volume;
-        if let Some(position) = position {
-            self.position = position*self.unit;
+        self.mixer.set_bus_volume(Category::Music.bus(), save.music_volume);
+        self.mixer.set_bus_volume(Category::Sfx.bus(), save.sfx_volume);
+        self.mixer.set_bus_volume(Category::Activator.bus(), save.activator_volume);
+        self.mixer.set_bus_volume(Category::Ui.bus(), save.ui_volume);
+        if let Some((position, velocity)) = listener {
+            self.position = position;
+            self.velocity = velocity;
         }
     }
+
+    /// Reverb of sfx and activators, None to disable it
+    pub fn set_reverb(&self, reverb: Option<&Reverb>) {
+        self.mixer.set_reverb(reverb.map(|reverb| reverb.settings()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Join, RunNow, World};
+    use std::io::Read;
+
+    const UPDATE_TIME: f32 = 1.0 / 60.0;
+    /// 10 seconds of updates
+    const UPDATES: usize = 600;
+    /// Distance of the turret on the right of the listener, half the audio unit
+    const TURRET_DISTANCE: f32 = 50.0;
+    /// Time after the beat in which a hit must reach its peak
+    const ONSET_TOLERANCE: f32 = 0.01;
+    const PAN_TOLERANCE: f64 = 0.05;
+
+    #[test]
+    fn turret_hits_on_beat_and_panned() {
+        let conf = ::resource::Conf::load();
+        let save = ::resource::Save {
+            audio_volume: 0.3,
+            music_volume: 0.0,
+            sfx_volume: 1.0,
+            activator_volume: 1.0,
+            ui_volume: 1.0,
+        };
+        let beat_time = conf.tempos[0];
+        let audio =
+            ::resource::Audio::init_offline(&conf, &save, super::DEFAULT_SAMPLE_RATE, true);
+        let latency = audio.latency;
+        let mut world = World::new();
+        ::init_world(&mut world, conf, save, audio);
+        world.maintain();
+        ::map::load_map("one".into(), &mut world).unwrap();
+        world.maintain();
+
+        // Only the first turret plays
+        let turret = (&*world.entities(), &world.read_storage::<::component::TurretSpawner>())
+            .join()
+            .next()
+            .unwrap()
+            .0;
+        let others = (&*world.entities(), &world.read_storage::<::component::Activators>())
+            .join()
+            .map(|(entity, _)| entity)
+            .filter(|&entity| entity != turret)
+            .collect::<Vec<_>>();
+        for entity in others {
+            world.entities().delete(entity).unwrap();
+        }
+        ::util::safe_maintain(&mut world);
+
+        let listener = {
+            let bodies = world.read_storage::<::component::RigidBody>();
+            let physic_world = world.read_resource::<::resource::PhysicWorld>();
+            bodies.get(turret).unwrap().get(&physic_world).position().translation.vector
+                - ::na::Vector2::new(TURRET_DISTANCE, 0.0)
+        };
+        {
+            let save = world.read_resource::<::resource::Save>();
+            world
+                .write_resource::<::resource::Audio>()
+                .update(Some((listener, ::na::zero())), &save);
+        }
+
+        world.write_resource::<::resource::UpdateTime>().0 = UPDATE_TIME;
+        for _ in 0..UPDATES {
+            world
+                .write_resource::<::resource::Audio>()
+                .advance_clock(UPDATE_TIME);
+            ::system::ActivatorSystem.run_now(&world.res);
+            ::system::TurretSpawnerSystem.run_now(&world.res);
+        }
+
+        let audio = world.read_resource::<::resource::Audio>();
+        let recorded = audio.recorded().unwrap();
+        let sample_rate = audio.sample_rate() as f32;
+        let channels = super::CHANNELS as usize;
+        let frames = recorded.len() / channels;
+        let peak = |start: usize, end: usize| {
+            recorded[start * channels..end.min(frames) * channels]
+                .iter()
+                .map(|&sample| (sample as i32).abs())
+                .max()
+                .unwrap_or(0)
+        };
+
+        // Silence until the first beat, delayed by the latency
+        let first_beat = (latency * sample_rate) as usize;
+        assert_eq!(peak(0, first_beat - 1), 0);
+
+        let tolerance = (ONSET_TOLERANCE * sample_rate) as usize;
+        let mut beat = 0;
+        loop {
+            let frame = ((beat as f32 * beat_time + latency) * sample_rate) as usize;
+            if frame + tolerance > frames {
+                break;
+            }
+            assert!(
+                peak(frame, frame + tolerance) > peak(frame - tolerance, frame),
+                "no hit on beat {}",
+                beat
+            );
+            beat += 1;
+        }
+        assert!(beat > 0);
+
+        // Pan of 0.75 at half the unit on the right: sqrt(0.25) / sqrt(0.75)
+        let (left, right) = recorded.chunks(channels).fold((0.0, 0.0), |(left, right), frame| {
+            (
+                left + (frame[0] as f64).abs(),
+                right + (frame[1] as f64).abs(),
+            )
+        });
+        assert!(right > 0.0);
+        assert!(
+            (left / right - (1.0f64 / 3.0).sqrt()).abs() < PAN_TOLERANCE,
+            "left/right ratio {}",
+            left / right
+        );
+    }
+
+    #[test]
+    fn wav_header_and_data() {
+        let mut path = ::std::env::temp_dir();
+        path.push("audio_write_wav_test.wav");
+        let samples = [0i16, 1, -1, ::std::i16::MAX, ::std::i16::MIN, 256];
+        super::write_wav(&path, 2, 22050, &samples).unwrap();
+
+        let mut bytes = vec![];
+        ::std::fs::File::open(&path)
+            .unwrap()
+            .read_to_end(&mut bytes)
+            .unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(bytes.len(), 44 + samples.len() * 2);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        // Channels, sample rate and bits per sample
+        assert_eq!(&bytes[22..24], &[2, 0]);
+        assert_eq!(&bytes[24..28], &[0x22, 0x56, 0, 0]);
+        assert_eq!(&bytes[34..36], &[16, 0]);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(&bytes[40..44], &[12, 0, 0, 0]);
+        assert_eq!(&bytes[44..], &[0, 0, 1, 0, 0xff, 0xff, 0xff, 0x7f, 0, 0x80, 0, 1]);
+    }
 }
@@ -6,7 +6,9 @@ use nphysics2d::math::Force;
 use nphysics2d::object::BodyStatus;
 use retained_storage::RetainedStorage;
 use specs::{Component, Entity, NullStorage, VecStorage, WriteStorage};
+use std::collections::HashMap;
 use std::f32::consts::PI;
+use std::path::PathBuf;
 use itertools::Itertools;
 
 #[derive(Deserialize, Clone, Default, Component)]
@@ -64,11 +66,211 @@ pub struct Activators(pub Vec<Activator>);
 #[derive(Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct Activator {
+    /// Index in the tempos of the configuration
+    ///
+    /// Ignored for MIDI partition: the tempo of the file is used
     pub tempo: usize,
-    pub partition: Vec<bool>,
+    pub partition: Partition,
     #[serde(skip)]
     pub activated: bool,
-    pub sound: ::audio::Sound,
+    /// Time of the activation relative to the current update, negative
+    #[serde(skip)]
+    pub activated_time: f32,
+    /// Name of the sound in the sound bank
+    pub sound: String,
+}
+
+impl Activator {
+    /// Empty if the MIDI partition is not loaded
+    pub fn beats(&self) -> &[bool] {
+        match self.partition {
+            Partition::Beats(ref beats) => beats,
+            Partition::Midi { .. } => &[],
+        }
+    }
+
+    pub fn is_activated_at(&self, beat: usize) -> bool {
+        let beats = self.beats();
+        !beats.is_empty() && beats[beat % beats.len()]
+    }
+
+    /// Replace MIDI partition by its beats and set the tempo of the file
+    ///
+    /// Tempos of loaded files are pushed to `tempos`
+    pub fn load_midi(
+        &mut self,
+        midi_tempos: &mut HashMap<PathBuf, (usize, ::midi::MidiFile)>,
+        tempos: &mut ::resource::Tempos,
+    ) -> Result<(), ::failure::Error> {
+        let beats = match self.partition {
+            Partition::Midi {
+                ref file,
+                track,
+                note,
+            } => {
+                if !midi_tempos.contains_key(file) {
+                    let midi_file = ::midi::MidiFile::load(file)?;
+                    tempos.push(midi_file.tempo());
+                    midi_tempos.insert(file.clone(), (tempos.len() - 1, midi_file));
+                }
+                let &(tempo, ref midi_file) = &midi_tempos[file];
+                self.tempo = tempo;
+                midi_file.partition(track, note).map_err(|e| {
+                    format_err!("\"{}\": {}", file.to_string_lossy(), e)
+                })?
+            }
+            Partition::Beats(_) => return Ok(()),
+        };
+        self.partition = Partition::Beats(beats);
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub enum Partition {
+    Beats(Vec<bool>),
+    /// Note on events of the note in the track of a standard MIDI file
+    ///
+    /// Loaded at map load, one beat per sixteenth note
+    Midi {
+        file: PathBuf,
+        track: usize,
+        note: u8,
+    },
+}
+
+/// Set on entities with a body inside the visibility polygon of the player,
+/// or on all of them if there is no player
+#[derive(Default, Component)]
+#[storage(NullStorage)]
+pub struct Visible;
+
+/// Sound following the entity, its volume, pan and filtering are updated every frame
+#[derive(Deserialize, Clone, Component)]
+#[serde(deny_unknown_fields)]
+#[storage(VecStorage)]
+pub struct SoundEmitter {
+    /// Name of the sound in the sound bank
+    pub sound: String,
+    /// Played in loop while the entity lives, otherwise played once
+    pub looping: bool,
+    /// Pitch is shifted depending on the relative velocity to the listener
+    #[serde(default)]
+    pub doppler: bool,
+    #[serde(skip)]
+    pub started: bool,
+    #[serde(skip)]
+    pub control: Option<::std::sync::Arc<::audio::EmitterControl>>,
+}
+
+/// Entity followed by the camera, the camera zooms out to fit all targets
+#[derive(Deserialize, Clone, Component)]
+#[serde(deny_unknown_fields)]
+#[storage(VecStorage)]
+pub struct CameraTarget {
+    /// Distance in world units the camera looks ahead in the direction of the aim
+    pub look_ahead: f32,
+}
+
+/// Particle emitters triggered by the entity, referenced by name in the particle bank
+#[derive(Deserialize, Clone, Component)]
+#[serde(deny_unknown_fields)]
+#[storage(VecStorage)]
+pub struct ParticleEffects {
+    /// Emitted when the entity dies
+    #[serde(default)]
+    pub death: Option<String>,
+    /// Emitted when the entity is damaged
+    #[serde(default)]
+    pub damage: Option<String>,
+    /// Emitted along the sword on each swing
+    #[serde(default)]
+    pub sword: Option<String>,
+    /// Emitted where the rifle bullets hit
+    #[serde(default)]
+    pub impact: Option<String>,
+    /// Emitted continuously while the entity lives
+    #[serde(default)]
+    pub ambient: Option<String>,
+}
+
+impl ParticleEffects {
+    pub fn emitters(&self) -> Vec<&String> {
+        [&self.death, &self.damage, &self.sword, &self.impact, &self.ambient]
+            .iter()
+            .filter_map(|emitter| emitter.as_ref())
+            .collect()
+    }
+}
+
+/// Activators of the entity follow their own clock instead of their tempo
+///
+/// When the activator is activated the clocks of the other coupled entities are nudged
+/// so that their closest activation of the activator gets closer to this one
+#[derive(Deserialize, Clone, Component)]
+#[serde(deny_unknown_fields)]
+#[storage(VecStorage)]
+pub struct RhythmCoupling {
+    pub activator: usize,
+    /// Natural time of a beat
+    pub time: f32,
+    /// The processor takes distance with the activated entity
+    /// The result is the part of the distance in beats to the closest activation that is nudged
+    pub coupling: ::util::ClampFunction,
+    /// Part of the coupling applied to the time of a beat, 0 is phase only
+    ///
+    /// The time of a beat also relaxes toward the natural time by this part on each beat
+    pub period_coupling: f32,
+    #[serde(skip)]
+    pub current_time: f32,
+    #[serde(skip)]
+    pub next_beat_time: f32,
+    #[serde(skip)]
+    pub beat: usize,
+}
+
+/// Smallest time of a beat relative to the natural time, so that coupling never stops the clock
+const RHYTHM_COUPLING_MIN_TIME_RATIO: f32 = 0.25;
+
+impl RhythmCoupling {
+    /// Shift the time of a beat, clamped to a part of the natural time
+    pub fn shift_time(&mut self, time_shift: f32) {
+        self.current_time = (self.current_time + time_shift)
+            .max(self.time * RHYTHM_COUPLING_MIN_TIME_RATIO);
+    }
+
+    /// Shift the clock by a number of beats
+    pub fn shift(&mut self, beats: f32) {
+        let offset = beats - self.next_beat_time / self.current_time;
+        let floor = offset.floor();
+        self.beat = (self.beat as isize + floor as isize + 1).max(0) as usize;
+        self.next_beat_time = (floor + 1.0 - offset) * self.current_time;
+    }
+
+    /// Signed distance in beats from the current time to the closest activation
+    pub fn distance_to_closest(&self, beats: &[bool]) -> Option<f32> {
+        let len = beats.len() as f32;
+        let mut position = (self.beat % beats.len().max(1)) as f32
+            - self.next_beat_time / self.current_time;
+        if position < 0.0 {
+            position += len;
+        }
+        beats
+            .iter()
+            .enumerate()
+            .filter(|&(_, &activated)| activated)
+            .map(|(i, _)| {
+                let mut distance = (i as f32 - position) % len;
+                if distance > len / 2.0 {
+                    distance -= len;
+                } else if distance <= -len / 2.0 {
+                    distance += len;
+                }
+                distance
+            })
+            .min_by_key(|distance| (distance.abs() * ::CMP_PRECISION) as usize)
+    }
 }
 
 //////////////////////////////// Life ////////////////////////////////
@@ -84,6 +286,12 @@ pub struct ContactDamage(pub usize);
 #[storage(NullStorage)]
 pub struct DeadOnContact;
 
+/// Shown on the minimap even out of the line of sight
+#[derive(Deserialize, Clone, Default, Component)]
+#[serde(deny_unknown_fields)]
+#[storage(NullStorage)]
+pub struct Revealed;
+
 #[derive(Deserialize, Clone, Deref, DerefMut, Component)]
 #[serde(deny_unknown_fields)]
 #[storage(VecStorage)]
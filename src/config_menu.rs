@@ -1,5 +1,43 @@
+use imgui::ImMouseButton;
+use show_message::UnwrapOrShow;
 use specs::World;
 
-pub fn build(ui: &::imgui::Ui, _world: &World) {
-    ui.window(im_str!("Configuration menu")).build(|| {});
+/// State of the configuration menu window
+pub struct ConfigMenu {
+    /// Volumes changed since the last write of the save
+    unsaved: bool,
+}
+
+impl ConfigMenu {
+    pub fn new() -> Self {
+        ConfigMenu { unsaved: false }
+    }
+}
+
+/// Volumes are applied while sliders are dragged, the save is written once released
+pub fn build(ui: &::imgui::Ui, world: &World) {
+    ui.window(im_str!("Configuration menu")).build(|| {
+        let mut menu = world.write_resource::<ConfigMenu>();
+        let mut save = world.write_resource::<::resource::Save>();
+        menu.unsaved |= ui
+            .slider_float(im_str!("Master volume"), &mut save.audio_volume, 0.0, 1.0)
+            .build();
+        menu.unsaved |= ui
+            .slider_float(im_str!("Music volume"), &mut save.music_volume, 0.0, 1.0)
+            .build();
+        menu.unsaved |= ui
+            .slider_float(im_str!("Effects volume"), &mut save.sfx_volume, 0.0, 1.0)
+            .build();
+        menu.unsaved |= ui
+            .slider_float(im_str!("Activators volume"), &mut save.activator_volume, 0.0, 1.0)
+            .build();
+        menu.unsaved |= ui
+            .slider_float(im_str!("Interface volume"), &mut save.ui_volume, 0.0, 1.0)
+            .build();
+        if menu.unsaved && !ui.imgui().is_mouse_down(ImMouseButton::Left) {
+            save.save()
+                .unwrap_or_else_show(|e| format!("Failed to write save: {}", e));
+            menu.unsaved = false;
+        }
+    });
 }
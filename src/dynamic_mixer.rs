@@ -1,4 +1,5 @@
 use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -8,13 +9,163 @@ use rodio::source::Source;
 
 use rodio::Sample;
 
+/// The ducked bus is attenuated when the level of the sidechain buses is loud
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DuckingSettings {
+    /// Level of the sidechain at which the ducking is full
+    pub threshold: f32,
+    /// Volume of the ducked bus when the ducking is full
+    pub volume: f32,
+    /// Time in seconds for the sidechain level to rise
+    pub attack: f32,
+    /// Time in seconds for the sidechain level to fall
+    pub release: f32,
+}
+
+/// Soft limiter on the master
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct LimiterSettings {
+    /// Maximal level of the output
+    pub threshold: f32,
+    /// Time in seconds for the gain to return to 1
+    pub release: f32,
+}
+
+/// Reverb of the sent buses, a reduced freeverb
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ReverbSettings {
+    /// Feedback of the comb filters in [0, 1[
+    pub room_size: f32,
+    /// Damping of high frequencies in the comb filters in [0, 1]
+    pub damping: f32,
+    /// Volume of the reverberated signal
+    pub wet: f32,
+}
+
+/// Delays of freeverb at 44100 Hz
+const COMB_TUNINGS: [usize; 4] = [1116, 1277, 1422, 1557];
+const ALLPASS_TUNINGS: [usize; 2] = [556, 341];
+/// Delay added to the right channel
+const STEREO_SPREAD: usize = 23;
+const REVERB_INPUT_GAIN: f32 = 0.03;
+
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+        self.filter_store = output * (1.0 - damping) + self.filter_store * damping;
+        self.buffer[self.index] = input + self.filter_store * feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn process(&mut self, input: f32) -> f32 {
+        let buffered = self.buffer[self.index];
+        self.buffer[self.index] = input + buffered * 0.5;
+        self.index = (self.index + 1) % self.buffer.len();
+        buffered - input
+    }
+}
+
+/// Combs and allpasses of one channel
+struct ReverbChannel {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+impl ReverbChannel {
+    fn new(sample_rate: u32, spread: usize) -> Self {
+        let scale = |tuning: usize| ((tuning + spread) as f32 * sample_rate as f32 / 44100.0) as usize;
+        ReverbChannel {
+            combs: COMB_TUNINGS
+                .iter()
+                .map(|&tuning| Comb {
+                    buffer: vec![0.0; scale(tuning).max(1)],
+                    index: 0,
+                    filter_store: 0.0,
+                })
+                .collect(),
+            allpasses: ALLPASS_TUNINGS
+                .iter()
+                .map(|&tuning| Allpass {
+                    buffer: vec![0.0; scale(tuning).max(1)],
+                    index: 0,
+                })
+                .collect(),
+        }
+    }
+
+    fn process(&mut self, input: f32, settings: &ReverbSettings) -> f32 {
+        let input = input * REVERB_INPUT_GAIN;
+        let mut output = 0.0;
+        for comb in &mut self.combs {
+            output += comb.process(input, settings.room_size, settings.damping);
+        }
+        for allpass in &mut self.allpasses {
+            output = allpass.process(output);
+        }
+        output * settings.wet
+    }
+}
+
+struct Reverb {
+    buses: Vec<usize>,
+    settings: Option<ReverbSettings>,
+    channels: Vec<ReverbChannel>,
+}
+
+/// Coefficient of a one pole filter reaching 63% of its target in time seconds
+fn smoothing(time: f32, channels: u16, sample_rate: u32) -> f32 {
+    (-1.0 / (time * channels as f32 * sample_rate as f32).max(1.0)).exp()
+}
+
+struct Ducking {
+    bus: usize,
+    sidechain: Vec<usize>,
+    threshold: f32,
+    volume: f32,
+    attack: f32,
+    release: f32,
+    envelope: f32,
+}
+
+struct Limiter {
+    threshold: f32,
+    release: f32,
+    envelope: f32,
+}
+
 /// Builds a new mixer.
 ///
 /// Added sources must follow specified channels and sample rate
 ///
 /// After creating a mixer, you can add new sounds with the controller.
+///
+/// Each source is mixed in one of the buses, their volumes are set from the controller.
+/// The ducking is defined by the ducked bus and the sidechain buses.
+/// The reverb is applied to the reverb buses once set from the controller.
 pub fn mixer<S>(
-    channels: u16, sample_rate: u32,
+    channels: u16,
+    sample_rate: u32,
+    buses: usize,
+    ducking: Option<(usize, Vec<usize>, DuckingSettings)>,
+    limiter: LimiterSettings,
+    reverb_buses: Vec<usize>,
 ) -> (Arc<DynamicMixerController<S>>, DynamicMixer<S>)
 where
     S: Sample + Send + 'static,
@@ -24,12 +175,43 @@ where
         pending_sources: Mutex::new(Vec::new()),
         channels: channels,
         sample_rate: sample_rate,
+        clock: AtomicUsize::new(0),
+        bus_volumes: (0..buses)
+            .map(|_| AtomicUsize::new(1f32.to_bits() as usize))
+            .collect(),
+        has_pending_reverb: AtomicBool::new(false),
+        pending_reverb: Mutex::new(None),
     });
 
     let output = DynamicMixer {
         current_sources: Vec::with_capacity(16),
+        scheduled_sources: vec![],
+        current_channel: 0,
         input: input.clone(),
         to_drop: vec![],
+        bus_sums: vec![0.0; buses],
+        bus_volumes: vec![1.0; buses],
+        ducking: ducking.map(|(bus, sidechain, settings)| Ducking {
+            bus,
+            sidechain,
+            threshold: settings.threshold.max(::std::f32::EPSILON),
+            volume: settings.volume,
+            attack: smoothing(settings.attack, channels, sample_rate),
+            release: smoothing(settings.release, channels, sample_rate),
+            envelope: 0.0,
+        }),
+        limiter: Limiter {
+            threshold: limiter.threshold.max(::std::f32::EPSILON),
+            release: smoothing(limiter.release, channels, sample_rate),
+            envelope: 0.0,
+        },
+        reverb: Reverb {
+            buses: reverb_buses,
+            settings: None,
+            channels: (0..channels)
+                .map(|channel| ReverbChannel::new(sample_rate, channel as usize * STEREO_SPREAD))
+                .collect(),
+        },
     };
 
     (input, output)
@@ -38,40 +220,87 @@ where
 /// The input of the mixer.
 pub struct DynamicMixerController<S> {
     has_pending: AtomicBool,
-    pending_sources: Mutex<Vec<Box<Source<Item = S> + Send>>>,
+    pending_sources: Mutex<Vec<(usize, usize, Box<Source<Item = S> + Send>)>>,
     channels: u16,
     sample_rate: u32,
+    // Number of frames outputted by the mixer
+    clock: AtomicUsize,
+    // Volume of each bus as f32 bits
+    bus_volumes: Vec<AtomicUsize>,
+    has_pending_reverb: AtomicBool,
+    pending_reverb: Mutex<Option<ReverbSettings>>,
 }
 
 impl<S> DynamicMixerController<S>
 where
     S: Sample + Send + 'static,
 {
-    /// Adds a new source to mix to the existing ones.
+    /// Adds a new source to mix to the existing ones in the bus.
     #[inline]
-    pub fn add<T>(&self, source: T)
+    pub fn add<T>(&self, source: T, bus: usize)
+    where
+        T: Source<Item = S> + Send + 'static,
+    {
+        self.add_at(source, bus, 0);
+    }
+
+    /// Adds a new source to mix to the existing ones in the bus starting at the frame of the clock.
+    ///
+    /// If the frame is already passed the source starts on the next frame.
+    #[inline]
+    pub fn add_at<T>(&self, source: T, bus: usize, frame: usize)
     where
         T: Source<Item = S> + Send + 'static,
     {
         assert_eq!(source.channels(), self.channels);
         assert_eq!(source.sample_rate(), self.sample_rate);
+        assert!(bus < self.bus_volumes.len());
 
         self.pending_sources
             .lock()
             .unwrap()
-            .push(Box::new(source));
+            .push((frame, bus, Box::new(source)));
         self.has_pending.store(true, Ordering::SeqCst); // TODO: can we relax this ordering?
     }
+
+    /// Number of frames outputted by the mixer.
+    #[inline]
+    pub fn clock(&self) -> usize {
+        self.clock.load(Ordering::Relaxed)
+    }
+
+    /// Set the reverb of the reverb buses, None to disable it.
+    pub fn set_reverb(&self, reverb: Option<ReverbSettings>) {
+        *self.pending_reverb.lock().unwrap() = reverb;
+        self.has_pending_reverb.store(true, Ordering::SeqCst);
+    }
+
+    #[inline]
+    pub fn set_bus_volume(&self, bus: usize, volume: f32) {
+        self.bus_volumes[bus].store(volume.to_bits() as usize, Ordering::Relaxed);
+    }
 }
 
 /// The output of the mixer. Implements `Source`.
 pub struct DynamicMixer<S> {
-    // The current iterator that produces samples.
-    current_sources: Vec<Box<Source<Item = S> + Send>>,
+    // The current iterator that produces samples with their bus.
+    current_sources: Vec<(usize, Box<Source<Item = S> + Send>)>,
     to_drop: Vec<usize>,
 
+    // The sources waiting for their frame with their bus.
+    scheduled_sources: Vec<(usize, usize, Box<Source<Item = S> + Send>)>,
+    // Sources are started on the first channel only.
+    current_channel: u16,
+
     // The pending sounds.
     input: Arc<DynamicMixerController<S>>,
+
+    bus_sums: Vec<f32>,
+    // Volumes of the buses loaded on each frame.
+    bus_volumes: Vec<f32>,
+    ducking: Option<Ducking>,
+    limiter: Limiter,
+    reverb: Reverb,
 }
 
 impl<S> Source for DynamicMixer<S>
@@ -107,17 +336,41 @@ where
 
     #[inline]
     fn next(&mut self) -> Option<S> {
-        if self.input.has_pending.load(Ordering::SeqCst) {
-            // TODO: relax ordering?
-            let mut pending = self.input.pending_sources.lock().unwrap();
-            self.current_sources.extend(pending.drain(..));
-            self.input.has_pending.store(false, Ordering::SeqCst); // TODO: relax ordering?
+        if self.current_channel == 0 {
+            if self.input.has_pending.load(Ordering::SeqCst) {
+                // TODO: relax ordering?
+                let mut pending = self.input.pending_sources.lock().unwrap();
+                self.scheduled_sources.extend(pending.drain(..));
+                self.input.has_pending.store(false, Ordering::SeqCst); // TODO: relax ordering?
+            }
+
+            let clock = self.input.clock();
+            let mut i = 0;
+            while i < self.scheduled_sources.len() {
+                if self.scheduled_sources[i].0 <= clock {
+                    let (_, bus, source) = self.scheduled_sources.swap_remove(i);
+                    self.current_sources.push((bus, source));
+                } else {
+                    i += 1;
+                }
+            }
+
+            if self.input.has_pending_reverb.load(Ordering::SeqCst) {
+                self.reverb.settings = self.input.pending_reverb.lock().unwrap().take();
+                self.input.has_pending_reverb.store(false, Ordering::SeqCst);
+            }
+
+            for (volume, control) in self.bus_volumes.iter_mut().zip(self.input.bus_volumes.iter()) {
+                *volume = f32::from_bits(control.load(Ordering::Relaxed) as u32);
+            }
         }
 
-        let mut sum = S::zero_value();
-        for (num, src) in self.current_sources.iter_mut().enumerate() {
+        for sum in &mut self.bus_sums {
+            *sum = 0.0;
+        }
+        for (num, &mut (bus, ref mut src)) in self.current_sources.iter_mut().enumerate() {
             if let Some(val) = src.next() {
-                sum = sum.saturating_add(val);
+                self.bus_sums[bus] += val.to_f32();
             } else {
                 self.to_drop.push(num);
             }
@@ -127,6 +380,57 @@ where
             self.current_sources.remove(td);
         }
 
+        for (sum, volume) in self.bus_sums.iter_mut().zip(self.bus_volumes.iter()) {
+            *sum *= *volume;
+        }
+
+        let bus_sums = &mut self.bus_sums;
+        if let Some(ref mut ducking) = self.ducking {
+            let level = ducking
+                .sidechain
+                .iter()
+                .map(|&bus| bus_sums[bus])
+                .sum::<f32>()
+                .abs();
+            let coefficient = if level > ducking.envelope {
+                ducking.attack
+            } else {
+                ducking.release
+            };
+            ducking.envelope = coefficient * ducking.envelope + (1.0 - coefficient) * level;
+
+            let ducking_amount = (ducking.envelope / ducking.threshold).min(1.0);
+            bus_sums[ducking.bus] *= 1.0 - (1.0 - ducking.volume) * ducking_amount;
+        }
+
+        let mut sum = bus_sums.iter().sum::<f32>();
+
+        if let Some(ref settings) = self.reverb.settings {
+            let input = self.reverb.buses.iter().map(|&bus| bus_sums[bus]).sum::<f32>();
+            sum += self.reverb.channels[self.current_channel as usize].process(input, settings);
+        }
+
+        // Instant attack so that the output never exceeds the threshold
+        let limiter = &mut self.limiter;
+        let peak = sum.abs();
+        if peak > limiter.envelope {
+            limiter.envelope = peak;
+        } else {
+            limiter.envelope = limiter.release * limiter.envelope + (1.0 - limiter.release) * peak;
+        }
+        let gain = if limiter.envelope > limiter.threshold {
+            limiter.threshold / limiter.envelope
+        } else {
+            1.0
+        };
+        let sum = S::from(&(sum * gain).min(1.0).max(-1.0));
+
+        self.current_channel += 1;
+        if self.current_channel == self.input.channels {
+            self.current_channel = 0;
+            self.input.clock.fetch_add(1, Ordering::Relaxed);
+        }
+
         Some(sum)
     }
 
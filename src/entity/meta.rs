@@ -1,4 +1,4 @@
-use animation::{AnimationName, AnimationSpecie};
+use animation::{AnimationName, AnimationSpecie, AppearanceConf};
 use component::*;
 use entity::{InsertPosition, Insertable};
 use ncollide2d::shape::{Ball, ShapeHandle};
@@ -23,6 +23,18 @@ macro_rules! component_list {
                     },)*
                 }
             }
+
+            /// Edit the components of the entity in the inspector
+            pub fn inspect(entity: Entity, world: &World, ui: &::imgui::Ui) {
+                use self::*;
+                let bounds = ::inspector::IndexBounds::new(entity, world);
+                $(if let Some(component) = world.write_storage::<$v>().get_mut(entity) {
+                    let label = ::imgui::ImString::new(stringify!($v));
+                    ui.tree_node(&label).build(|| {
+                        ::inspector::Inspect::inspect(component, ui, &bounds);
+                    });
+                })*
+            }
         }
     )
 }
@@ -48,15 +60,20 @@ component_list!{
     TurretSpawner,
     DebugColor,
     Activators,
+    RhythmCoupling,
     SwordRifle,
     PositionInPath,
+    SoundEmitter,
+    ParticleEffects,
+    CameraTarget,
+    Revealed,
 }
 
 #[derive(Deserialize, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct MetaOverride {
-    meta: String,
-    components: Vec<MetaComponent>,
+    pub(super) meta: String,
+    pub(super) components: Vec<MetaComponent>,
 }
 
 impl Insertable for MetaOverride {
@@ -90,6 +107,8 @@ pub struct Meta {
     #[serde(with = "::util::BodyStatusDef")]
     pub status: BodyStatus,
     pub groups: Vec<super::Group>,
+    #[serde(default)]
+    pub appearance: AppearanceConf,
     pub components: Vec<MetaComponent>,
 }
 
@@ -97,12 +116,15 @@ impl Insertable for Meta {
     fn insert(&self, mut position: InsertPosition, world: &World) -> Entity {
         let entity = world.entities().create();
 
+        let mut animation_state =
+            ::component::AnimationState::new(self.animation_specie, AnimationName::Idle);
+        animation_state.set_appearance(
+            &self.appearance,
+            &world.read_resource::<::resource::Conf>(),
+        );
         world
             .write_storage()
-            .insert(
-                entity,
-                ::component::AnimationState::new(self.animation_specie, AnimationName::Idle),
-            )
+            .insert(entity, animation_state)
             .unwrap();
 
         for component in &self.components {
@@ -110,8 +132,6 @@ impl Insertable for Meta {
             component.insert(entity, world);
         }
 
-        // TODO: debug circles for components
-
         if self.components.iter().any(|c| match c {
             MetaComponent::ContactDamage(_)
             | MetaComponent::VelocityToPlayerCircle(_)
@@ -124,12 +144,6 @@ impl Insertable for Meta {
                 .unwrap();
         }
 
-        // TODO: rays
-        // let mut rays = vec![];
-        // if !rays.is_empty() {
-        //     world.write_storage().insert(entity, ::component::DebugRays(rays));
-        // }
-
         if let Some(ref mut sword_rifle) = world
             .write_storage::<::component::SwordRifle>()
             .get_mut(entity)
@@ -137,6 +151,13 @@ impl Insertable for Meta {
             sword_rifle.compute_shapes();
         }
 
+        if let Some(ref mut rhythm_coupling) = world
+            .write_storage::<::component::RhythmCoupling>()
+            .get_mut(entity)
+        {
+            rhythm_coupling.current_time = rhythm_coupling.time;
+        }
+
         if let Some(ref mut position_in_path) = world
             .write_storage::<::component::PositionInPath>()
             .get_mut(entity)
@@ -6,7 +6,7 @@ use lyon::tessellation::geometry_builder::simple_builder;
 use lyon::tessellation::{FillOptions, FillTessellator, FillVertex, VertexBuffers};
 use specs::{Entity, World};
 
-#[derive(Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy)]
 #[serde(deny_unknown_fields)]
 #[repr(usize)]
 pub enum Group {
@@ -268,6 +268,41 @@ object!(
     SegmentableObject { Wall }
 );
 
+impl InsertableObject {
+    pub fn components(&self) -> &Vec<MetaComponent> {
+        match self {
+            &InsertableObject::Meta(ref meta) => &meta.components,
+            &InsertableObject::MetaOverride(ref meta_override) => &meta_override.components,
+        }
+    }
+
+    /// Components of the inserted entity, including the ones of the overridden meta
+    pub fn all_components<'a>(
+        &'a self,
+        insertables: &'a ::resource::InsertablesMap,
+    ) -> Vec<&'a MetaComponent> {
+        match self {
+            &InsertableObject::Meta(ref meta) => meta.components.iter().collect(),
+            &InsertableObject::MetaOverride(ref meta_override) => {
+                let base = match insertables.get(&meta_override.meta) {
+                    Some(&InsertableObject::Meta(ref meta)) => &meta.components[..],
+                    _ => &[],
+                };
+                base.iter().chain(meta_override.components.iter()).collect()
+            }
+        }
+    }
+
+    pub fn components_mut(&mut self) -> &mut Vec<MetaComponent> {
+        match self {
+            &mut InsertableObject::Meta(ref mut meta) => &mut meta.components,
+            &mut InsertableObject::MetaOverride(ref mut meta_override) => {
+                &mut meta_override.components
+            }
+        }
+    }
+}
+
 mod wall;
 pub use self::wall::Wall;
 
@@ -1,4 +1,5 @@
 use gilrs::{Button, EventType};
+use show_message::UnwrapOrShow;
 use specs::{Join, World};
 use std::f32::EPSILON;
 use winit::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
@@ -93,7 +94,8 @@ impl GameState for Game {
                     },
                 ..
             } => {
-                ::map::load_map("one".into(), world).unwrap();
+                ::map::load_map("one".into(), world)
+                    .unwrap_or_else_show(|e| format!("Failed to load map: {}", e));
             }
             ::winit::Event::WindowEvent {
                 event:
@@ -1,19 +1,18 @@
 use alga::general::SubsetOf;
-use itertools::Itertools;
-use ncollide2d::shape::{self, Segment, ShapeHandle};
-use specs::Join;
+use rusttype::gpu_cache::{Cache, CacheBuilder};
+use rusttype::{Font, PositionedGlyph};
+use render::{DebugVertex, RenderBackend, SpriteInstance};
 use specs::World;
 use std::cell::RefCell;
 use std::f32::consts::PI;
 use std::fs::File;
+use std::io::Read;
 use std::sync::Arc;
 use std::time::Duration;
 use vulkano::buffer::{BufferUsage, CpuAccessibleBuffer, CpuBufferPool, ImmutableBuffer};
 use vulkano::command_buffer::pool::standard::StandardCommandPoolAlloc;
 use vulkano::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder, DynamicState};
-use vulkano::descriptor::descriptor_set::{
-    DescriptorSet, FixedSizeDescriptorSetsPool, PersistentDescriptorSet,
-};
+use vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet};
 use vulkano::device::{Device, DeviceExtensions, Queue};
 use vulkano::format::{self, ClearValue, Format};
 use vulkano::framebuffer::{
@@ -22,8 +21,10 @@ use vulkano::framebuffer::{
     StoreOp, Subpass,
 };
 use vulkano::image::ImageLayout;
-use vulkano::image::{AttachmentImage, Dimensions, ImageUsage, ImmutableImage};
+use vulkano::image::{AttachmentImage, Dimensions, ImageUsage, ImmutableImage, StorageImage};
 use vulkano::instance::PhysicalDevice;
+use vulkano::pipeline::blend::AttachmentBlend;
+use vulkano::pipeline::vertex::OneVertexOneInstanceDefinition;
 use vulkano::pipeline::viewport::Viewport;
 use vulkano::pipeline::{GraphicsPipeline, GraphicsPipelineAbstract};
 use vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
@@ -32,22 +33,86 @@ use vulkano::sync::{now, FlushError, GpuFuture};
 
 // TODO: only a bool for whereas draw the cursor or not
 
-const DEBUG_SEGMENT_WIDTH: f32 = 0.2;
-const DEBUG_RAY_LENGTH: f32 = 10.0;
+/// Color drawn over what is outside of the visibility polygon
+const FOG_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.7];
+const GLYPH_CACHE_SIZE: u32 = 1024;
+/// Outline width relative to the text size
+const TEXT_OUTLINE_WIDTH: f32 = 0.06;
 
 pub struct Camera {
     pub position: ::na::Isometry2<f32>,
+    /// Half of the height seen in world units, or of the width if the window is higher than wide
     pub zoom: f32,
+    /// Amount of shake in [0, 1], decreases over time
+    pub trauma: f32,
 }
 
 impl Camera {
     pub fn new(position: ::na::Isometry2<f32>, zoom: f32) -> Self {
-        Camera { position, zoom }
+        Camera {
+            position,
+            zoom,
+            trauma: 0.0,
+        }
+    }
+
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CameraSettings {
+    /// Spring constant pulling the camera to its target
+    pub stiffness: f32,
+    /// Damping of the spring, critical damping is 2 * sqrt(stiffness)
+    pub damping: f32,
+    /// Speed of the convergence of the zoom
+    pub zoom_smoothing: f32,
+    /// Factor applied to the zoom depending on the speed of the targets
+    pub speed_zoom: ::util::ClampFunction,
+    /// Margin in world units kept around targets when zooming to fit them
+    pub fit_margin: f32,
+    /// Trauma lost per second
+    pub trauma_decay: f32,
+    /// Maximal shift of the shake in world units
+    pub shake_translation: f32,
+    /// Maximal rotation of the shake in radians
+    pub shake_rotation: f32,
+    /// Frequency of the shake
+    pub shake_frequency: f32,
+    /// Trauma added when the player is damaged
+    pub damage_trauma: f32,
+    /// Trauma added when the sword hits
+    pub sword_trauma: f32,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub enum CameraZoneMode {
+    /// The camera shows the whole zone
+    Lock,
+    /// The camera doesn't show outside of the zone
+    Bound,
+}
+
+/// Zone of the map constraining the camera when the first target is inside
+pub struct CameraZone {
+    pub min: ::na::Vector2<f32>,
+    pub max: ::na::Vector2<f32>,
+    pub mode: CameraZoneMode,
+}
+
+impl CameraZone {
+    pub fn contains(&self, point: ::na::Vector2<f32>) -> bool {
+        point[0] >= self.min[0] && point[0] <= self.max[0] && point[1] >= self.min[1]
+            && point[1] <= self.max[1]
     }
 }
 
 impl Camera {
-    fn matrix(&self, dimensions: [u32; 2]) -> [[f32; 4]; 4] {
+    pub fn matrix(&self, dimensions: [u32; 2]) -> [[f32; 4]; 4] {
         let rescale_trans = {
             let ratio = dimensions[0] as f32 / dimensions[1] as f32;
 
@@ -75,12 +140,29 @@ impl Camera {
 
         (rescale_trans * world_trans).unwrap().into()
     }
-}
 
-struct Image {
-    descriptor_set: Arc<DescriptorSet + Sync + Send>,
-    width: u32,
-    height: u32,
+    /// Position in pixels from the top left corner of the window
+    fn world_to_screen(&self, position: ::na::Vector2<f32>, dimensions: [u32; 2]) -> [f32; 2] {
+        let matrix = ::na::Matrix4::from(self.matrix(dimensions));
+        let position = matrix * ::na::Vector4::new(position[0], -position[1], 0.0, 1.0);
+        [
+            (position[0] + 1.0) / 2.0 * dimensions[0] as f32,
+            (position[1] + 1.0) / 2.0 * dimensions[1] as f32,
+        ]
+    }
+
+    /// Position in the world of a point in pixels from the top left corner of the window
+    pub fn screen_to_world(&self, position: [f32; 2], dimensions: [u32; 2]) -> ::na::Vector2<f32> {
+        let matrix = ::na::Matrix4::from(self.matrix(dimensions));
+        let inverse = matrix.try_inverse().unwrap_or_else(::na::one);
+        let position = inverse * ::na::Vector4::new(
+            position[0] / dimensions[0] as f32 * 2.0 - 1.0,
+            position[1] / dimensions[1] as f32 * 2.0 - 1.0,
+            0.0,
+            1.0,
+        );
+        ::na::Vector2::new(position[0], -position[1])
+    }
 }
 
 pub struct Graphics {
@@ -88,14 +170,34 @@ pub struct Graphics {
     device: Arc<Device>,
     swapchain: Arc<Swapchain<::winit::Window>>,
     render_pass: Arc<RenderPassAbstract + Sync + Send>,
+    /// Draw one quad instance per animation image
     pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
     debug_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    /// Write the visibility polygon in the depth buffer only
+    fog_mask_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    /// Draw the fog where the depth buffer is not masked
+    fog_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    /// Draw one quad instance per particle
+    particle_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    /// Plain white texture used by particles without sprite
+    particle_white_descriptor_set: Arc<DescriptorSet + Sync + Send>,
+    /// Draw the HUD shapes, in normalized device coordinates
+    hud_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    /// Draw text glyphs from the glyph cache texture, in normalized device coordinates
+    text_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
+    text_descriptor_set: Arc<DescriptorSet + Sync + Send>,
+    font: Font<'static>,
+    glyph_cache: Cache<'static>,
+    glyph_cache_image: Arc<StorageImage<format::R8Unorm>>,
     vertex_buffer: Arc<ImmutableBuffer<[Vertex]>>,
-    animation_images: Vec<Image>,
+    /// Generation of the map geometry uploaded in the map vertex buffers
+    map_generation: usize,
+    /// Background then walls, none if empty
+    map_vertex_buffers: Vec<Option<Arc<ImmutableBuffer<[DebugVertex]>>>>,
+    /// One descriptor set per page of the animation atlas
+    atlas_pages: Vec<Arc<DescriptorSet + Sync + Send>>,
     framebuffers: Vec<Arc<FramebufferAbstract + Sync + Send>>,
     view_buffer_pool: CpuBufferPool<vs::ty::View>,
-    world_buffer_pool: CpuBufferPool<vs::ty::World>,
-    descriptor_sets_pool: FixedSizeDescriptorSetsPool<Arc<GraphicsPipelineAbstract + Sync + Send>>,
     future: Option<Box<GpuFuture>>,
 
     imgui_pipeline: Arc<GraphicsPipelineAbstract + Sync + Send>,
@@ -108,12 +210,29 @@ struct Vertex {
 }
 impl_vertex!(Vertex, position);
 
+impl_vertex!(DebugVertex, position, color);
+
+impl_vertex!(
+    SpriteInstance,
+    position,
+    angle,
+    size,
+    layer,
+    uv_min,
+    uv_max,
+    color,
+    flash
+);
+
+impl_vertex!(::particle::ParticleInstance, center, size, color, uv_min, uv_max);
+
 #[derive(Debug, Clone)]
-struct DebugVertex {
+struct TextVertex {
     position: [f32; 2],
+    tex_coords: [f32; 2],
     color: [f32; 4],
 }
-impl_vertex!(DebugVertex, position, color);
+impl_vertex!(TextVertex, position, tex_coords, color);
 
 #[derive(Debug, Clone)]
 pub struct ImGuiVertex {
@@ -136,6 +255,85 @@ impl From<::imgui::ImDrawVert> for ImGuiVertex {
         }
     }
 }
+/// Record the drawing of the world in the command buffer
+struct VulkanBackend<'a> {
+    graphics: &'a mut Graphics,
+    builder: Option<AutoCommandBufferBuilder>,
+    dynamic_state: DynamicState,
+    sprite_sets: Arc<DescriptorSet + Sync + Send>,
+    debug_sets: Arc<DescriptorSet + Sync + Send>,
+}
+
+impl<'a> RenderBackend for VulkanBackend<'a> {
+    fn draw_map(&mut self, map_geometry: &::resource::MapGeometry) {
+        self.graphics.update_map_vertex_buffers(map_geometry);
+        let vertex_buffers = self.graphics
+            .map_vertex_buffers
+            .iter()
+            .filter_map(|b| b.clone())
+            .collect::<Vec<_>>();
+        for vertex_buffer in vertex_buffers {
+            self.builder = Some(
+                self.builder
+                    .take()
+                    .unwrap()
+                    .draw(
+                        self.graphics.debug_pipeline.clone(),
+                        self.dynamic_state.clone(),
+                        vec![vertex_buffer],
+                        self.debug_sets.clone(),
+                        (),
+                    )
+                    .unwrap(),
+            );
+        }
+    }
+
+    fn draw_sprites(&mut self, page: usize, instances: Vec<SpriteInstance>) {
+        let instance_buffer = CpuAccessibleBuffer::from_iter(
+            self.graphics.device.clone(),
+            BufferUsage::vertex_buffer(),
+            instances.into_iter(),
+        ).expect("failed to create buffer");
+
+        self.builder = Some(
+            self.builder
+                .take()
+                .unwrap()
+                .draw(
+                    self.graphics.pipeline.clone(),
+                    self.dynamic_state.clone(),
+                    (self.graphics.vertex_buffer.clone(), instance_buffer),
+                    (self.sprite_sets.clone(), self.graphics.atlas_pages[page].clone()),
+                    (),
+                )
+                .unwrap(),
+        );
+    }
+
+    fn draw_triangles(&mut self, vertices: Vec<DebugVertex>) {
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            self.graphics.device.clone(),
+            BufferUsage::vertex_buffer(),
+            vertices.into_iter(),
+        ).expect("failed to create buffer");
+
+        self.builder = Some(
+            self.builder
+                .take()
+                .unwrap()
+                .draw(
+                    self.graphics.debug_pipeline.clone(),
+                    self.dynamic_state.clone(),
+                    vec![vertex_buffer],
+                    self.debug_sets.clone(),
+                    (),
+                )
+                .unwrap(),
+        );
+    }
+}
+
 impl Graphics {
     pub fn new(window: &Arc<Surface<::winit::Window>>, imgui: &mut ::imgui::ImGui) -> Graphics {
         let physical = PhysicalDevice::enumerate(&window.instance())
@@ -229,6 +427,22 @@ impl Graphics {
         let debug_fs =
             debug_fs::Shader::load(device.clone()).expect("failed to create shader module");
 
+        let fog_vs =
+            fog_vs::Shader::load(device.clone()).expect("failed to create shader module");
+
+        let particle_vs =
+            particle_vs::Shader::load(device.clone()).expect("failed to create shader module");
+        let particle_fs =
+            particle_fs::Shader::load(device.clone()).expect("failed to create shader module");
+
+        let hud_vs =
+            hud_vs::Shader::load(device.clone()).expect("failed to create shader module");
+
+        let text_vs =
+            text_vs::Shader::load(device.clone()).expect("failed to create shader module");
+        let text_fs =
+            text_fs::Shader::load(device.clone()).expect("failed to create shader module");
+
         let imgui_vs =
             imgui_vs::Shader::load(device.clone()).expect("failed to create shader module");
         let imgui_fs =
@@ -236,11 +450,10 @@ impl Graphics {
 
         let pipeline = Arc::new(
             GraphicsPipeline::start()
-                .vertex_input_single_buffer::<Vertex>()
+                .vertex_input(OneVertexOneInstanceDefinition::<Vertex, SpriteInstance>::new())
                 .vertex_shader(vs.main_entry_point(), ())
-                .triangle_strip()
+                .triangle_list()
                 .viewports_dynamic_scissors_irrelevant(1)
-                .cull_mode_back()
                 .fragment_shader(fs.main_entry_point(), ())
                 .blend_alpha_blending()
                 .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
@@ -261,6 +474,76 @@ impl Graphics {
                 .unwrap(),
         );
 
+        let fog_mask_pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input_single_buffer::<DebugVertex>()
+                .vertex_shader(fog_vs.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(debug_fs.main_entry_point(), ())
+                .depth_stencil_simple_depth()
+                .blend_collective(AttachmentBlend::ignore_source())
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .build(device.clone())
+                .unwrap(),
+        );
+
+        let fog_pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input_single_buffer::<DebugVertex>()
+                .vertex_shader(fog_vs.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(debug_fs.main_entry_point(), ())
+                .depth_stencil_simple_depth()
+                .blend_alpha_blending()
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .build(device.clone())
+                .unwrap(),
+        );
+
+        let particle_pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input(OneVertexOneInstanceDefinition::<
+                    Vertex,
+                    ::particle::ParticleInstance,
+                >::new())
+                .vertex_shader(particle_vs.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(particle_fs.main_entry_point(), ())
+                .blend_alpha_blending()
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .build(device.clone())
+                .unwrap(),
+        );
+
+        let hud_pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input_single_buffer::<DebugVertex>()
+                .vertex_shader(hud_vs.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(debug_fs.main_entry_point(), ())
+                .blend_alpha_blending()
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .build(device.clone())
+                .unwrap(),
+        );
+
+        let text_pipeline = Arc::new(
+            GraphicsPipeline::start()
+                .vertex_input_single_buffer::<TextVertex>()
+                .vertex_shader(text_vs.main_entry_point(), ())
+                .triangle_list()
+                .viewports_dynamic_scissors_irrelevant(1)
+                .fragment_shader(text_fs.main_entry_point(), ())
+                .blend_alpha_blending()
+                .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+                .build(device.clone())
+                .unwrap(),
+        );
+
         let imgui_pipeline = Arc::new(
             GraphicsPipeline::start()
                 .vertex_input_single_buffer::<ImGuiVertex>()
@@ -275,8 +558,6 @@ impl Graphics {
                 .unwrap(),
         );
 
-        let descriptor_sets_pool = FixedSizeDescriptorSetsPool::new(pipeline.clone() as Arc<_>, 0);
-
         let imgui_texture = imgui
             .prepare_texture(|handle| {
                 ImmutableImage::from_iter(
@@ -317,8 +598,6 @@ impl Graphics {
             )
         };
 
-        let mut animation_images = vec![];
-
         let sampler = Sampler::new(
             device.clone(),
             Filter::Linear,
@@ -333,45 +612,89 @@ impl Graphics {
             0.0,
         ).unwrap();
 
-        for image_path in &::animation::ANIMATIONS.images {
-            let file = File::open(image_path).unwrap();
-            let (info, mut reader) = ::png::Decoder::new(file).read_info().unwrap();
-            assert_eq!(info.color_type, ::png::ColorType::RGBA);
-            let mut buf = vec![0; info.buffer_size()];
-            reader.next_frame(&mut buf).unwrap();
-
+        let mut atlas_pages = vec![];
+        for page in &::animation::ANIMATIONS.atlas.pages {
             let (image, image_fut) = ImmutableImage::from_iter(
-                buf.into_iter(),
+                page.pixels.iter().cloned(),
                 Dimensions::Dim2d {
-                    width: info.width,
-                    height: info.height,
+                    width: page.width,
+                    height: page.height,
                 },
                 format::R8G8B8A8Srgb,
                 queue.clone(),
             ).unwrap();
             future = Box::new(future.join(image_fut)) as Box<_>;
 
-            let descriptor_set = Arc::new(
+            atlas_pages.push(Arc::new(
                 PersistentDescriptorSet::start(pipeline.clone(), 1)
-                    .add_sampled_image(image.clone(), sampler.clone())
+                    .add_sampled_image(image, sampler.clone())
                     .unwrap()
                     .build()
                     .unwrap(),
-            ) as Arc<_>;
-
-            animation_images.push(Image {
-                descriptor_set,
-                width: info.width,
-                height: info.height,
-            })
+            ) as Arc<_>);
         }
 
+        let particle_white_descriptor_set = {
+            let (image, image_fut) = ImmutableImage::from_iter(
+                [255u8; 4].iter().cloned(),
+                Dimensions::Dim2d {
+                    width: 1,
+                    height: 1,
+                },
+                format::R8G8B8A8Srgb,
+                queue.clone(),
+            ).unwrap();
+            future = Box::new(future.join(image_fut)) as Box<_>;
+
+            Arc::new(
+                PersistentDescriptorSet::start(particle_pipeline.clone(), 1)
+                    .add_sampled_image(image, sampler.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            ) as Arc<_>
+        };
+
+        let font = {
+            let mut bytes = vec![];
+            File::open(::text::FONT_PATH)
+                .and_then(|mut file| file.read_to_end(&mut bytes))
+                .expect("failed to read font");
+            Font::from_bytes(bytes).expect("failed to load font")
+        };
+
+        let glyph_cache = CacheBuilder {
+            width: GLYPH_CACHE_SIZE,
+            height: GLYPH_CACHE_SIZE,
+            ..CacheBuilder::default()
+        }.build();
+
+        let glyph_cache_image = StorageImage::with_usage(
+            device.clone(),
+            Dimensions::Dim2d {
+                width: GLYPH_CACHE_SIZE,
+                height: GLYPH_CACHE_SIZE,
+            },
+            format::R8Unorm,
+            ImageUsage {
+                transfer_destination: true,
+                sampled: true,
+                ..ImageUsage::none()
+            },
+            Some(queue.family()),
+        ).unwrap();
+
+        let text_descriptor_set = Arc::new(
+            PersistentDescriptorSet::start(text_pipeline.clone(), 0)
+                .add_sampled_image(glyph_cache_image.clone(), sampler.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        ) as Arc<_>;
+
         let view_buffer_pool =
             CpuBufferPool::<vs::ty::View>::new(device.clone(), BufferUsage::uniform_buffer());
 
-        let world_buffer_pool =
-            CpuBufferPool::<vs::ty::World>::new(device.clone(), BufferUsage::uniform_buffer());
-
         let depth_buffer_attachment = AttachmentImage::transient(
             device.clone(),
             images[0].dimensions(),
@@ -403,12 +726,22 @@ impl Graphics {
             render_pass,
             pipeline,
             debug_pipeline,
+            fog_mask_pipeline,
+            fog_pipeline,
+            particle_pipeline,
+            particle_white_descriptor_set,
+            hud_pipeline,
+            text_pipeline,
+            text_descriptor_set,
+            font,
+            glyph_cache,
+            glyph_cache_image,
             vertex_buffer,
-            animation_images,
+            map_generation: 0,
+            map_vertex_buffers: vec![],
+            atlas_pages,
             framebuffers,
             view_buffer_pool,
-            world_buffer_pool,
-            descriptor_sets_pool,
             imgui_pipeline,
             imgui_descriptor_set,
         }
@@ -468,6 +801,147 @@ impl Graphics {
             .collect::<Vec<_>>();
     }
 
+    fn update_map_vertex_buffers(&mut self, map_geometry: &::resource::MapGeometry) {
+        if map_geometry.generation == self.map_generation {
+            return;
+        }
+        self.map_generation = map_geometry.generation;
+
+        let mut future = self.future.take().unwrap();
+        self.map_vertex_buffers = [&map_geometry.background, &map_geometry.walls]
+            .iter()
+            .map(|vertices| {
+                if vertices.is_empty() {
+                    return None;
+                }
+                let (vertex_buffer, vertex_buffer_fut) = ImmutableBuffer::from_iter(
+                    vertices.iter().map(|v| DebugVertex {
+                        position: [v.position[0], -v.position[1]],
+                        color: v.color,
+                    }),
+                    BufferUsage::vertex_buffer(),
+                    self.queue.clone(),
+                ).expect("failed to create buffer");
+                future = Box::new(future.join(vertex_buffer_fut)) as Box<_>;
+                Some(vertex_buffer)
+            })
+            .collect();
+        self.future = Some(future);
+    }
+
+    /// Drain the texts, cache their glyphs and return their vertices
+    fn text_vertices(
+        &mut self,
+        world: &World,
+        dimensions: [u32; 2],
+        mut command_buffer_builder: AutoCommandBufferBuilder,
+    ) -> (
+        AutoCommandBufferBuilder,
+        Vec<TextVertex>,
+    ) {
+        let camera = world.read_resource::<::resource::Camera>();
+        let mut glyphs: Vec<(PositionedGlyph<'static>, [f32; 4])> = vec![];
+        for text in world.write_resource::<::resource::Texts>().drain(..) {
+            let (origin, size) = match text.space {
+                ::text::Space::Screen => ([text.position[0], text.position[1]], text.size),
+                ::text::Space::World => {
+                    let origin = camera.world_to_screen(text.position, dimensions);
+                    let top = camera.world_to_screen(
+                        text.position + ::na::Vector2::new(0.0, text.size),
+                        dimensions,
+                    );
+                    let size = ((top[0] - origin[0]).powi(2) + (top[1] - origin[1]).powi(2)).sqrt();
+                    (origin, size)
+                }
+            };
+
+            let mut layers = vec![];
+            if let Some(outline) = text.outline {
+                let width = (size * TEXT_OUTLINE_WIDTH).max(1.0);
+                for i in 0..8 {
+                    let angle = i as f32 * PI / 4.0;
+                    let shifted = [
+                        origin[0] + angle.cos() * width,
+                        origin[1] + angle.sin() * width,
+                    ];
+                    layers.push((shifted, outline));
+                }
+            }
+            layers.push((origin, text.color));
+
+            for (origin, color) in layers {
+                for glyph in ::text::layout(&self.font, &text.string, size, origin, text.align) {
+                    glyphs.push((glyph, color));
+                }
+            }
+        }
+
+        if glyphs.is_empty() {
+            return (command_buffer_builder, vec![]);
+        }
+
+        for &(ref glyph, _) in &glyphs {
+            self.glyph_cache.queue_glyph(0, glyph.clone());
+        }
+        let mut uploads = vec![];
+        if let Err(e) = self.glyph_cache.cache_queued(|rect, data| {
+            uploads.push((rect, data.to_vec()));
+        }) {
+            println!("ERROR: glyph cache: {:?}", e);
+            return (command_buffer_builder, vec![]);
+        }
+
+        for (rect, data) in uploads {
+            let buffer = CpuAccessibleBuffer::from_iter(
+                self.device.clone(),
+                BufferUsage::transfer_source(),
+                data.into_iter(),
+            ).expect("failed to create buffer");
+            command_buffer_builder = command_buffer_builder
+                .copy_buffer_to_image_dimensions(
+                    buffer,
+                    self.glyph_cache_image.clone(),
+                    [rect.min.x, rect.min.y, 0],
+                    [rect.width(), rect.height(), 1],
+                    0,
+                    1,
+                    0,
+                )
+                .unwrap();
+        }
+
+        let to_ndc = |x: i32, y: i32| {
+            [
+                x as f32 / dimensions[0] as f32 * 2.0 - 1.0,
+                y as f32 / dimensions[1] as f32 * 2.0 - 1.0,
+            ]
+        };
+        let mut vertices = vec![];
+        for (glyph, color) in glyphs {
+            if let Ok(Some((uv, screen))) = self.glyph_cache.rect_for(0, &glyph) {
+                let min = to_ndc(screen.min.x, screen.min.y);
+                let max = to_ndc(screen.max.x, screen.max.y);
+                vertices.extend(
+                    [
+                        ([min[0], min[1]], [uv.min.x, uv.min.y]),
+                        ([max[0], min[1]], [uv.max.x, uv.min.y]),
+                        ([max[0], max[1]], [uv.max.x, uv.max.y]),
+                        ([min[0], min[1]], [uv.min.x, uv.min.y]),
+                        ([max[0], max[1]], [uv.max.x, uv.max.y]),
+                        ([min[0], max[1]], [uv.min.x, uv.max.y]),
+                    ].iter()
+                        .map(|&(position, tex_coords)| TextVertex {
+                            position,
+                            tex_coords,
+                            color,
+                        }),
+                );
+            }
+        }
+
+        (command_buffer_builder, vertices)
+    }
+
     fn build_command_buffer(
         &mut self,
         image_num: usize,
@@ -484,10 +958,16 @@ impl Graphics {
             ..DynamicState::none()
         };
 
-        let mut command_buffer_builder = AutoCommandBufferBuilder::primary_one_time_submit(
+        let command_buffer_builder = AutoCommandBufferBuilder::primary_one_time_submit(
             self.device.clone(),
             self.queue.family(),
-        ).unwrap()
+        ).unwrap();
+
+        // Upload glyphs of texts, must be done outside of the render pass
+        let (command_buffer_builder, text_vertices) =
+            self.text_vertices(world, dimensions, command_buffer_builder);
+
+        let mut command_buffer_builder = command_buffer_builder
             .begin_render_pass(
                 self.framebuffers[image_num].clone(),
                 false,
@@ -503,118 +983,191 @@ impl Graphics {
         };
         let view_buffer = self.view_buffer_pool.next(view).unwrap();
 
-        let mut images = world.write_resource::<::resource::AnimationImages>();
-        for image in images.drain(..) {
-            let world_matrix: ::na::Transform3<f32> = ::na::Isometry3::<f32>::new(
-                ::na::Vector3::new(
-                    image.position.translation.vector[0],
-                    -image.position.translation.vector[1],
-                    0.0,
-                ),
-                ::na::Vector3::new(0.0, 0.0, -image.position.rotation.angle()),
-            ).to_superset();
-
-            let world = vs::ty::World {
-                world: world_matrix.unwrap().into(),
+        // Draw map, animation images and debug shapes
+        let mut command_buffer_builder = {
+            let sprite_sets = Arc::new(
+                PersistentDescriptorSet::start(self.pipeline.clone(), 0)
+                    .add_buffer(view_buffer.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            );
+            let debug_sets = Arc::new(
+                PersistentDescriptorSet::start(self.debug_pipeline.clone(), 0)
+                    .add_buffer(view_buffer.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            );
+            let mut backend = VulkanBackend {
+                graphics: self,
+                builder: Some(command_buffer_builder),
+                dynamic_state: screen_dynamic_state.clone(),
+                sprite_sets,
+                debug_sets,
             };
-            let world_buffer = self.world_buffer_pool.next(world).unwrap();
+            ::render::draw_world(world, &mut backend);
+            backend.builder.take().unwrap()
+        };
+        world
+            .write_resource::<::resource::AnimationImages>()
+            .clear();
+        world.write_resource::<::resource::DebugShapes>().clear();
 
-            let sets = self.descriptor_sets_pool
-                .next()
-                .add_buffer(view_buffer.clone())
-                .unwrap()
-                .add_buffer(world_buffer)
-                .unwrap()
-                .build()
-                .unwrap();
+        // Draw particles
+        {
+            let sets = Arc::new(
+                PersistentDescriptorSet::start(self.particle_pipeline.clone(), 0)
+                    .add_buffer(view_buffer.clone())
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            );
 
-            command_buffer_builder = command_buffer_builder
-                .draw(
-                    self.pipeline.clone(),
-                    screen_dynamic_state.clone(),
-                    vec![self.vertex_buffer.clone()],
-                    (sets, self.animation_images[image.id].descriptor_set.clone()),
-                    vs::ty::Info {
-                        layer: image.layer,
-                        height: self.animation_images[image.id].height as f32,
-                        width: self.animation_images[image.id].width as f32,
-                    },
-                )
-                .unwrap()
+            for (page, instances) in world.read_resource::<::resource::Particles>().instances() {
+                let instance_buffer = CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    BufferUsage::vertex_buffer(),
+                    instances.into_iter(),
+                ).expect("failed to create buffer");
+
+                let sprite_set = match page {
+                    Some(page) => self.atlas_pages[page].clone(),
+                    None => self.particle_white_descriptor_set.clone(),
+                };
+
+                command_buffer_builder = command_buffer_builder
+                    .draw(
+                        self.particle_pipeline.clone(),
+                        screen_dynamic_state.clone(),
+                        (self.vertex_buffer.clone(), instance_buffer),
+                        (sets.clone(), sprite_set),
+                        (),
+                    )
+                    .unwrap()
+            }
         }
 
-        // Draw physic world
-        if true {
+        // Draw fog of war
+        if let Some(ref polygon) = world.read_resource::<::resource::Visibility>().0 {
+            let mask_vertex_buffer = CpuAccessibleBuffer::from_iter(
+                self.device.clone(),
+                BufferUsage::vertex_buffer(),
+                polygon
+                    .triangles()
+                    .iter()
+                    .flat_map(|triangle| triangle.iter())
+                    .map(|p| DebugVertex {
+                        position: [p[0], -p[1]],
+                        color: FOG_COLOR,
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            ).expect("failed to create buffer");
+
+            let fog_vertex_buffer = CpuAccessibleBuffer::from_iter(
+                self.device.clone(),
+                BufferUsage::vertex_buffer(),
+                [
+                    [-1.0f32, -1.0],
+                    [1.0, -1.0],
+                    [-1.0, 1.0],
+                    [-1.0, 1.0],
+                    [1.0, -1.0],
+                    [1.0, 1.0],
+                ].iter()
+                    .map(|&position| DebugVertex {
+                        position,
+                        color: FOG_COLOR,
+                    }),
+            ).expect("failed to create buffer");
+
             let sets = Arc::new(
-                PersistentDescriptorSet::start(self.debug_pipeline.clone(), 0)
+                PersistentDescriptorSet::start(self.fog_mask_pipeline.clone(), 0)
                     .add_buffer(view_buffer.clone())
                     .unwrap()
                     .build()
                     .unwrap(),
             );
 
-            let bodies_map = world.read_resource::<::resource::BodiesMap>();
-            let debug_colors = world.read_storage::<::component::DebugColor>();
-            let aims = world.read_storage::<::component::Aim>();
-            let debug_circles = world.read_storage::<::component::DebugCircles>();
-            let debug_rays = world.read_storage::<::component::DebugRays>();
-            let bodies = world.read_storage::<::component::RigidBody>();
-            let physic_world = world.read_resource::<::resource::PhysicWorld>();
-            let mut debug_shapes = world.write_resource::<::resource::DebugShapes>();
+            // The fog is drawn in screen coordinates
+            let screen_view_buffer = self.view_buffer_pool
+                .next(vs::ty::View {
+                    view: ::na::Matrix4::<f32>::identity().into(),
+                })
+                .unwrap();
+            let screen_sets = Arc::new(
+                PersistentDescriptorSet::start(self.fog_pipeline.clone(), 0)
+                    .add_buffer(screen_view_buffer)
+                    .unwrap()
+                    .build()
+                    .unwrap(),
+            );
 
-            let mut vertices = vec![];
-            for collider in physic_world.colliders() {
-                let color = bodies_map
-                    .get(&collider.data().body())
-                    .and_then(|e| debug_colors.get(*e))
-                    .map(|c| c.0)
-                    .unwrap_or(0);
-                shape_vertices(
-                    collider.position(),
-                    collider.shape(),
-                    COLORS[color],
-                    &mut vertices,
-                );
-            }
-            for (radiuss, color, body) in (&debug_circles, &debug_colors, &bodies).join() {
-                let body = body.get(&physic_world);
-                for &radius in radiuss.iter() {
-                    circle_vertices(&body.position(), radius, COLORS[color.0], &mut vertices);
-                }
-            }
-            for (position, shape) in debug_shapes.drain(..) {
-                shape_vertices(&position, &shape, COLORS[0], &mut vertices);
-            }
+            command_buffer_builder = command_buffer_builder
+                .draw(
+                    self.fog_mask_pipeline.clone(),
+                    screen_dynamic_state.clone(),
+                    vec![mask_vertex_buffer],
+                    sets,
+                    fog_vs::ty::Depth { depth: 0.0 },
+                )
+                .unwrap()
+                .draw(
+                    self.fog_pipeline.clone(),
+                    screen_dynamic_state.clone(),
+                    vec![fog_vertex_buffer],
+                    screen_sets,
+                    fog_vs::ty::Depth { depth: 0.5 },
+                )
+                .unwrap()
+        }
 
-            let segment = ShapeHandle::new(Segment::new(
-                ::na::Point2::new(0.0, 0.0),
-                ::na::Point2::new(DEBUG_RAY_LENGTH, 0.0),
-            ));
-            for (rays, body, color) in (&debug_rays, &bodies, &debug_colors).join() {
-                let position = body.get(&physic_world).position();
-                for &ray in rays.iter() {
-                    let position = position * ::na::UnitComplex::new(ray);
-                    shape_vertices(&position, &segment, COLORS[color.0], &mut vertices);
-                }
+        // Draw HUD
+        {
+            let mut vertices = vec![];
+            for shape in world.write_resource::<::resource::HudShapes>().drain(..) {
+                shape.triangles(&mut vertices);
             }
-            for (aim, body, color) in (&aims, &bodies, &debug_colors).join() {
-                let position = body.get(&physic_world).position();
-                let position = position * ::na::UnitComplex::new(aim.0);
-                shape_vertices(&position, &segment, COLORS[color.0], &mut vertices);
+            if !vertices.is_empty() {
+                let hud_vertex_buffer = CpuAccessibleBuffer::from_iter(
+                    self.device.clone(),
+                    BufferUsage::vertex_buffer(),
+                    vertices.into_iter().map(|(p, color)| DebugVertex {
+                        position: [
+                            p[0] / dimensions[0] as f32 * 2.0 - 1.0,
+                            p[1] / dimensions[1] as f32 * 2.0 - 1.0,
+                        ],
+                        color,
+                    }),
+                ).expect("failed to create buffer");
+
+                command_buffer_builder = command_buffer_builder
+                    .draw(
+                        self.hud_pipeline.clone(),
+                        screen_dynamic_state.clone(),
+                        vec![hud_vertex_buffer],
+                        (),
+                        (),
+                    )
+                    .unwrap()
             }
+        }
 
-            let vertex_buffer = CpuAccessibleBuffer::from_iter(
+        // Draw texts
+        if !text_vertices.is_empty() {
+            let text_vertex_buffer = CpuAccessibleBuffer::from_iter(
                 self.device.clone(),
                 BufferUsage::vertex_buffer(),
-                vertices.drain(..),
+                text_vertices.into_iter(),
             ).expect("failed to create buffer");
 
             command_buffer_builder = command_buffer_builder
                 .draw(
-                    self.debug_pipeline.clone(),
+                    self.text_pipeline.clone(),
                     screen_dynamic_state.clone(),
-                    vec![vertex_buffer],
-                    sets.clone(),
+                    vec![text_vertex_buffer],
+                    self.text_descriptor_set.clone(),
                     (),
                 )
                 .unwrap()
@@ -632,6 +1185,7 @@ impl Graphics {
             );
 
             ::config_menu::build(&ui, world);
+            ::inspector::build(&ui, world);
 
             let ref_cell_cmd_builder = RefCell::new(Some(command_buffer_builder));
             ui.render::<_, ()>(|ui, drawlist| {
@@ -732,179 +1286,135 @@ impl Graphics {
     }
 }
 
-const DIV: usize = 16;
+mod vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
 
-lazy_static! {
-    static ref DISK: Vec<::na::Point2<f32>> = (0..DIV + 1)
-        .flat_map(|i| {
-            let a1 = i as f32 * 2.0 * PI / DIV as f32;
-            let a2 = (i + 1) as f32 * 2.0 * PI / DIV as f32;
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 center;
+layout(location = 2) in float angle;
+layout(location = 3) in vec2 size;
+layout(location = 4) in float layer;
+layout(location = 5) in vec2 uv_min;
+layout(location = 6) in vec2 uv_max;
+layout(location = 7) in vec4 color;
+layout(location = 8) in float flash;
 
-            vec![
-                ::na::Point2::new(a1.cos(), a1.sin()),
-                ::na::Point2::new(a2.cos(), a2.sin()),
-                ::na::Point2::new(0.0, 0.0),
-            ]
-        })
-        .collect::<Vec<_>>();
-}
+layout(location = 0) out vec2 tex_coords;
+layout(location = 1) out vec4 v_color;
+layout(location = 2) out float v_flash;
 
-lazy_static! {
-    static ref CIRCLE: Vec<::na::Point2<f32>> = (0..DIV + 1)
-        .flat_map(|i| {
-            let outer_radius = 1.05;
-            let inner_radius = 0.95;
+layout(set = 0, binding = 0) uniform View {
+    mat4 view;
+} view;
 
-            let a1 = i as f32 * 2.0 * PI / DIV as f32;
-            let a2 = (i + 1) as f32 * 2.0 * PI / DIV as f32;
+void main() {
+    vec2 p = position * size;
+    vec2 rotated = vec2(cos(angle) * p.x - sin(angle) * p.y, sin(angle) * p.x + cos(angle) * p.y);
+    gl_Position = view.view * vec4(center + rotated, layer, 1.0);
+    tex_coords = mix(uv_min, uv_max, position + vec2(0.5));
+    v_color = color;
+    v_flash = flash;
+}
+"]
+    struct _Dummy;
+}
 
-            let p1 = ::na::Point2::new(a1.cos(), a1.sin());
-            let p2 = ::na::Point2::new(a2.cos(), a2.sin());
+mod fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[src = "
+#version 450
 
-            let inner_p1 = p1 * inner_radius;
-            let outer_p1 = p1 * outer_radius;
+layout(location = 0) in vec2 tex_coords;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) in float v_flash;
+layout(location = 0) out vec4 f_color;
 
-            let inner_p2 = p2 * inner_radius;
-            let outer_p2 = p2 * outer_radius;
+layout(set = 1, binding = 0) uniform sampler2D tex;
 
-            vec![outer_p1, inner_p1, outer_p2, outer_p2, inner_p2, inner_p1]
-        })
-        .collect::<Vec<_>>();
+void main() {
+    vec4 color = texture(tex, tex_coords) * v_color;
+    f_color = vec4(mix(color.rgb, vec3(1.0), v_flash), color.a);
 }
-
-lazy_static! {
-    static ref COLORS: Vec<[f32; 4]> = vec![
-        [1.0, 0.0, 0.0, 1.0],
-        [1.0, 1.0, 0.0, 1.0],
-        [0.0, 1.0, 0.0, 1.0],
-        [0.0, 1.0, 1.0, 1.0],
-        [0.0, 0.0, 1.0, 1.0],
-        [1.0, 0.0, 1.0, 1.0],
-        [1.0, 1.0, 1.0, 1.0],
-    ];
+"]
+    struct _Dummy;
 }
 
-fn circle_vertices(
-    position: &::na::Isometry2<f32>,
-    radius: f32,
-    color: [f32; 4],
-    vertices: &mut Vec<DebugVertex>,
-) {
-    vertices.extend(CIRCLE.iter().map(|p| *p * radius).map(|p| {
-        let p = position * p;
-        DebugVertex {
-            position: [p[0], -p[1]],
-            color,
-        }
-    }));
-}
-fn shape_vertices(
-    position: &::na::Isometry2<f32>,
-    shape: &ShapeHandle<f32>,
-    color: [f32; 4],
-    vertices: &mut Vec<DebugVertex>,
-) {
-    if let Some(ball) = shape.as_shape::<shape::Ball<f32>>() {
-        vertices.extend(DISK.iter().map(|p| *p * ball.radius()).map(|p| {
-            let p = position * p;
-            DebugVertex {
-                position: [p[0], -p[1]],
-                color,
-            }
-        }));
-    }
-    if let Some(convex_polygon) = shape.as_shape::<shape::ConvexPolygon<f32>>() {
-        let mut points_iter = convex_polygon.points().iter();
-        let pivot = points_iter.next().unwrap();
-        vertices.extend(
-            points_iter
-                .tuple_windows()
-                .flat_map(|(p1, p2)| vec![pivot, p1, p2])
-                .map(|p| {
-                    let p = position * *p;
-                    DebugVertex {
-                        position: [p[0], -p[1]],
-                        color,
-                    }
-                })
-                .collect::<Vec<_>>(),
-        );
-    }
-    if let Some(segment) = shape.as_shape::<shape::Segment<f32>>() {
-        let direction = segment.scaled_direction().normalize();
-        let normal = ::na::Vector2::new(-direction[1], direction[0]);
+mod debug_vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
 
-        vertices.extend(
-            [
-                segment.a() + normal * DEBUG_SEGMENT_WIDTH / 2.0,
-                segment.a() - normal * DEBUG_SEGMENT_WIDTH / 2.0,
-                segment.b() - normal * DEBUG_SEGMENT_WIDTH / 2.0,
-                segment.b() + normal * DEBUG_SEGMENT_WIDTH / 2.0,
-                segment.b() - normal * DEBUG_SEGMENT_WIDTH / 2.0,
-                segment.a() + normal * DEBUG_SEGMENT_WIDTH / 2.0,
-            ].iter()
-                .map(|p| {
-                    let p = position * *p;
-                    DebugVertex {
-                        position: [p[0], -p[1]],
-                        color,
-                    }
-                })
-                .collect::<Vec<_>>(),
-        );
-    }
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+
+layout(set = 0, binding = 0) uniform View {
+    mat4 view;
+} view;
+
+void main() {
+    gl_Position = view.view * vec4(position, 0.1, 1.0);
+    v_color = color;
+}
+"]
+    struct _Dummy;
 }
 
-mod vs {
+mod particle_vs {
     #[derive(VulkanoShader)]
     #[ty = "vertex"]
     #[src = "
 #version 450
 
 layout(location = 0) in vec2 position;
-layout(location = 0) out vec2 tex_coords;
+layout(location = 1) in vec2 center;
+layout(location = 2) in float size;
+layout(location = 3) in vec4 color;
+layout(location = 4) in vec2 uv_min;
+layout(location = 5) in vec2 uv_max;
 
-layout(push_constant) uniform Info {
-    float layer;
-    float height;
-    float width;
-} info;
+layout(location = 0) out vec2 tex_coords;
+layout(location = 1) out vec4 v_color;
 
 layout(set = 0, binding = 0) uniform View {
     mat4 view;
 } view;
 
-layout(set = 0, binding = 1) uniform World {
-    mat4 world;
-} world;
-
 void main() {
-    gl_Position = view.view * world.world * vec4(position[0]*info.width, position[1]*info.height, info.layer, 1.0);
-    tex_coords = position + vec2(0.5);
+    gl_Position = view.view * vec4(center + position * size, 0.2, 1.0);
+    tex_coords = mix(uv_min, uv_max, position + vec2(0.5));
+    v_color = color;
 }
 "]
     struct _Dummy;
 }
 
-mod fs {
+mod particle_fs {
     #[derive(VulkanoShader)]
     #[ty = "fragment"]
     #[src = "
 #version 450
 
 layout(location = 0) in vec2 tex_coords;
+layout(location = 1) in vec4 v_color;
 layout(location = 0) out vec4 f_color;
 
 layout(set = 1, binding = 0) uniform sampler2D tex;
 
 void main() {
-    f_color = texture(tex, tex_coords);
+    f_color = texture(tex, tex_coords) * v_color;
 }
 "]
     struct _Dummy;
 }
 
-mod debug_vs {
+mod hud_vs {
     #[derive(VulkanoShader)]
     #[ty = "vertex"]
     #[src = "
@@ -915,18 +1425,55 @@ layout(location = 1) in vec4 color;
 
 layout(location = 0) out vec4 v_color;
 
-layout(set = 0, binding = 0) uniform View {
-    mat4 view;
-} view;
+void main() {
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_color = color;
+}
+"]
+    struct _Dummy;
+}
+
+mod text_vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 tex_coords;
+layout(location = 2) in vec4 color;
+
+layout(location = 0) out vec2 v_tex_coords;
+layout(location = 1) out vec4 v_color;
 
 void main() {
-    gl_Position = view.view * vec4(position, 0.1, 1.0);
+    gl_Position = vec4(position, 0.0, 1.0);
+    v_tex_coords = tex_coords;
     v_color = color;
 }
 "]
     struct _Dummy;
 }
 
+mod text_fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec2 v_tex_coords;
+layout(location = 1) in vec4 v_color;
+layout(location = 0) out vec4 f_color;
+
+layout(set = 0, binding = 0) uniform sampler2D tex;
+
+void main() {
+    f_color = vec4(v_color.rgb, v_color.a * texture(tex, v_tex_coords).r);
+}
+"]
+    struct _Dummy;
+}
+
 mod debug_fs {
     #[derive(VulkanoShader)]
     #[ty = "fragment"]
@@ -944,6 +1491,33 @@ void main() {
     struct _Dummy;
 }
 
+mod fog_vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+
+layout(push_constant) uniform Depth {
+    float depth;
+} depth;
+
+layout(set = 0, binding = 0) uniform View {
+    mat4 view;
+} view;
+
+void main() {
+    gl_Position = view.view * vec4(position, depth.depth, 1.0);
+    v_color = color;
+}
+"]
+    struct _Dummy;
+}
+
 mod imgui_vs {
     #[derive(VulkanoShader)]
     #[ty = "vertex"]
@@ -0,0 +1,153 @@
+use std::f32::consts::PI;
+
+/// Number of triangles of a full circle
+const CIRCLE_DIVISIONS: usize = 32;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MinimapSettings {
+    pub corner: Corner,
+    /// Side of the minimap in pixels
+    pub size: f32,
+    /// World units per pixel
+    pub zoom: f32,
+    /// Groups of the entities shown as dots
+    #[serde(default)]
+    pub groups: Vec<::entity::Group>,
+    /// Show entities only in the line of sight of the player, revealed ones are always shown
+    pub line_of_sight: bool,
+    /// Angle of the aim cone of the player
+    pub aim_cone: f32,
+    /// Length of the aim cone in pixels
+    pub aim_length: f32,
+}
+
+/// Shape of the HUD in pixels from the top left corner of the window
+pub enum HudShape {
+    Rectangle {
+        min: [f32; 2],
+        max: [f32; 2],
+        color: [f32; 4],
+    },
+    Disk {
+        center: [f32; 2],
+        radius: f32,
+        color: [f32; 4],
+    },
+    Triangle {
+        points: [[f32; 2]; 3],
+        color: [f32; 4],
+    },
+    Segment {
+        a: [f32; 2],
+        b: [f32; 2],
+        width: f32,
+        color: [f32; 4],
+    },
+    /// Part of a ring starting at the top and going clockwise, progress in [0, 1]
+    Ring {
+        center: [f32; 2],
+        radius: f32,
+        width: f32,
+        progress: f32,
+        color: [f32; 4],
+    },
+}
+
+impl HudShape {
+    /// Push the triangles of the shape as (position, color) vertices
+    pub fn triangles(&self, vertices: &mut Vec<([f32; 2], [f32; 4])>) {
+        match *self {
+            HudShape::Rectangle { min, max, color } => {
+                for &p in &[
+                    [min[0], min[1]],
+                    [max[0], min[1]],
+                    [max[0], max[1]],
+                    [min[0], min[1]],
+                    [max[0], max[1]],
+                    [min[0], max[1]],
+                ] {
+                    vertices.push((p, color));
+                }
+            }
+            HudShape::Disk {
+                center,
+                radius,
+                color,
+            } => {
+                let point = |i: usize| {
+                    let angle = i as f32 * 2.0 * PI / CIRCLE_DIVISIONS as f32;
+                    [
+                        center[0] + angle.cos() * radius,
+                        center[1] + angle.sin() * radius,
+                    ]
+                };
+                for i in 0..CIRCLE_DIVISIONS {
+                    vertices.push((center, color));
+                    vertices.push((point(i), color));
+                    vertices.push((point(i + 1), color));
+                }
+            }
+            HudShape::Triangle { points, color } => {
+                for &p in &points {
+                    vertices.push((p, color));
+                }
+            }
+            HudShape::Segment { a, b, width, color } => {
+                let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+                let length = (dx * dx + dy * dy).sqrt();
+                if length <= ::std::f32::EPSILON {
+                    return;
+                }
+                let normal = [-dy / length * width / 2.0, dx / length * width / 2.0];
+                let a1 = [a[0] + normal[0], a[1] + normal[1]];
+                let a2 = [a[0] - normal[0], a[1] - normal[1]];
+                let b1 = [b[0] + normal[0], b[1] + normal[1]];
+                let b2 = [b[0] - normal[0], b[1] - normal[1]];
+                for &p in &[a1, a2, b2, a1, b2, b1] {
+                    vertices.push((p, color));
+                }
+            }
+            HudShape::Ring {
+                center,
+                radius,
+                width,
+                progress,
+                color,
+            } => {
+                let progress = progress.min(1.0).max(0.0);
+                let divisions = (progress * CIRCLE_DIVISIONS as f32).ceil() as usize;
+                let point = |i: usize, radius: f32| {
+                    let angle =
+                        i as f32 / divisions as f32 * progress * 2.0 * PI - PI / 2.0;
+                    [
+                        center[0] + angle.cos() * radius,
+                        center[1] + angle.sin() * radius,
+                    ]
+                };
+                let (inner, outer) = (radius - width / 2.0, radius + width / 2.0);
+                for i in 0..divisions {
+                    vertices.push((point(i, inner), color));
+                    vertices.push((point(i, outer), color));
+                    vertices.push((point(i + 1, outer), color));
+                    vertices.push((point(i, inner), color));
+                    vertices.push((point(i + 1, outer), color));
+                    vertices.push((point(i + 1, inner), color));
+                }
+            }
+        }
+    }
+}
+
+/// Shapes of the HUD drawn on the next frame, drained by the graphics
+#[derive(Deref, DerefMut)]
+pub struct HudShapes(pub Vec<HudShape>);
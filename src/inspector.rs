@@ -0,0 +1,474 @@
+use component::*;
+use entity::{Insertable, InsertPosition};
+use imgui::{ImGuiSelectableFlags, ImMouseButton, ImStr, ImString, Ui};
+use ncollide2d::world::CollisionGroups;
+use nphysics2d::algebra::Velocity2;
+use nphysics2d::object::BodyHandle;
+use specs::{Entity, Join, World};
+
+/// State of the entity inspector window
+pub struct Inspector {
+    pub open: bool,
+    pub selected: Option<Entity>,
+    /// Index of the insertable spawned at the cursor in the sorted insertables
+    pub spawn: i32,
+}
+
+impl Inspector {
+    pub fn new() -> Self {
+        Inspector {
+            open: false,
+            selected: None,
+            spawn: 0,
+        }
+    }
+}
+
+/// Edit the fields of a component in the inspector
+pub trait Inspect {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds);
+}
+
+/// Number of elements indexed by the fields of the inspected entity
+pub struct IndexBounds {
+    /// Activators of the entity
+    pub activators: usize,
+    pub tempos: usize,
+    pub colors: usize,
+}
+
+impl IndexBounds {
+    pub fn new(entity: Entity, world: &World) -> Self {
+        IndexBounds {
+            activators: world
+                .read_storage::<Activators>()
+                .get(entity)
+                .map(|activators| activators.0.len())
+                .unwrap_or(0),
+            tempos: world.read_resource::<::resource::Tempos>().0.len(),
+            colors: ::render::COLORS.len(),
+        }
+    }
+}
+
+fn edit_usize(ui: &Ui, label: &str, value: &mut usize) {
+    let mut int = *value as i32;
+    if ui.input_int(im_str!("{}", label), &mut int).build() {
+        *value = int.max(0) as usize;
+    }
+}
+
+/// Edit an index, rejecting values out of `0..len`
+fn edit_index(ui: &Ui, label: &str, value: &mut usize, len: usize) {
+    let mut int = *value as i32;
+    if ui.input_int(im_str!("{} (< {})", label, len), &mut int).build()
+        && int >= 0
+        && (int as usize) < len
+    {
+        *value = int as usize;
+    }
+}
+
+fn edit_f32(ui: &Ui, label: &str, value: &mut f32) {
+    ui.input_float(im_str!("{}", label), value).build();
+}
+
+fn edit_clamp(ui: &Ui, label: &str, clamp: &mut ::util::ClampFunction) {
+    let mut values = [clamp.min_t, clamp.max_t, clamp.min_value, clamp.max_value];
+    if ui.input_float4(im_str!("{} [t0 t1 v0 v1]", label), &mut values)
+        .build() && values[0] < values[1]
+    {
+        clamp.min_t = values[0];
+        clamp.max_t = values[1];
+        clamp.min_value = values[2];
+        clamp.max_value = values[3];
+    }
+}
+
+macro_rules! inspect_nothing {
+    ($($t:ident,)*) => (
+        $(impl Inspect for $t {
+            fn inspect(&mut self, _ui: &Ui, _bounds: &IndexBounds) {}
+        })*
+    )
+}
+
+inspect_nothing!{
+    Player,
+    DeadOnContact,
+    Revealed,
+}
+
+impl Inspect for Aim {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_f32(ui, "angle", &mut self.0);
+    }
+}
+
+impl Inspect for ContactDamage {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_usize(ui, "damage", &mut self.0);
+    }
+}
+
+impl Inspect for Life {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_usize(ui, "life", &mut self.0);
+    }
+}
+
+impl Inspect for VelocityControl {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_f32(ui, "velocity", &mut self.velocity);
+        ui.text(im_str!("direction: {:.2} {:.2}", self.direction[0], self.direction[1]));
+    }
+}
+
+impl Inspect for VelocityToPlayerMemory {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        edit_index(ui, "activator", &mut self.activator, bounds.activators);
+        edit_f32(ui, "velocity", &mut self.velocity);
+        ui.checkbox(im_str!("memory"), &mut self.memory);
+        match self.last_closest_in_sight {
+            Some(position) => ui.text(im_str!(
+                "last closest in sight: {:.2} {:.2}",
+                position[0],
+                position[1]
+            )),
+            None => ui.text(im_str!("last closest in sight: none")),
+        }
+    }
+}
+
+impl Inspect for VelocityToPlayerRandom {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        edit_index(ui, "activator", &mut self.activator, bounds.activators);
+        edit_f32(ui, "velocity", &mut self.velocity);
+        ui.checkbox(im_str!("toward player"), &mut self.toward_player);
+        edit_clamp(ui, "dist proba clamp", &mut self.dist_proba_clamp);
+        edit_clamp(ui, "aim proba clamp", &mut self.aim_proba_clamp);
+        if let Some(ref mut weight) = self.random_weighted {
+            edit_f32(ui, "random weight", weight);
+        }
+    }
+}
+
+impl Inspect for VelocityToPlayerCircle {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        edit_index(ui, "activator", &mut self.activator, bounds.activators);
+        edit_f32(ui, "circle velocity", &mut self.circle_velocity);
+        edit_f32(ui, "direct velocity", &mut self.direct_velocity);
+        ui.checkbox(im_str!("dir shift"), &mut self.dir_shift);
+    }
+}
+
+impl Inspect for VelocityDistanceDamping {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_clamp(ui, "clamp", &mut self.0);
+    }
+}
+
+impl Inspect for VelocityAimDamping {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_clamp(ui, "clamp", &mut self.0);
+    }
+}
+
+impl Inspect for PlayersAimDamping {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_clamp(ui, "clamp", &mut self.0);
+    }
+}
+
+impl Inspect for PlayersDistanceDamping {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_clamp(ui, "clamp", &mut self.0);
+    }
+}
+
+impl Inspect for GravityToPlayers {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_f32(ui, "force", &mut self.force);
+        ui.input_int(im_str!("powi"), &mut self.powi).build();
+    }
+}
+
+impl Inspect for Damping {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_f32(ui, "linear", &mut self.linear);
+        edit_f32(ui, "angular", &mut self.angular);
+    }
+}
+
+impl Inspect for UniqueSpawner {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        edit_index(ui, "activator", &mut self.activator, bounds.activators);
+        ui.text(im_str!("spawn: {}", self.spawn));
+        if let Some(ref mut clamp) = self.dist_proba_clamp {
+            edit_clamp(ui, "dist proba clamp", clamp);
+        }
+        if let Some(ref mut clamp) = self.aim_proba_clamp {
+            edit_clamp(ui, "aim proba clamp", clamp);
+        }
+    }
+}
+
+impl Inspect for ChamanSpawner {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        edit_index(ui, "activator", &mut self.activator, bounds.activators);
+        edit_usize(ui, "number of spawn", &mut self.number_of_spawn);
+        ui.text(im_str!("spawn: {}", self.spawn));
+        ui.text(im_str!("spawned: {}", self.spawned.len()));
+    }
+}
+
+impl Inspect for TurretSpawner {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        for (i, part) in self.0.iter_mut().enumerate() {
+            ui.tree_node(im_str!("part {}", i)).build(|| {
+                edit_index(ui, "activator", &mut part.activator, bounds.activators);
+                edit_usize(ui, "rotation time", &mut part.rotation_time);
+                edit_f32(ui, "shoot distance", &mut part.shoot_distance);
+                ui.checkbox(im_str!("clockwise"), &mut part.clockwise);
+                ui.text(im_str!("spawn: {}", part.spawn));
+            });
+        }
+    }
+}
+
+impl Inspect for DebugColor {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        edit_index(ui, "color", &mut self.0, bounds.colors);
+    }
+}
+
+impl Inspect for Activators {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        for (i, activator) in self.0.iter_mut().enumerate() {
+            ui.tree_node(im_str!("activator {}", i)).build(|| {
+                ui.text(im_str!("sound: {}", activator.sound));
+                edit_index(ui, "tempo", &mut activator.tempo, bounds.tempos);
+                match activator.partition {
+                    Partition::Beats(ref mut beats) => {
+                        for (beat, activated) in beats.iter_mut().enumerate() {
+                            if beat % 8 != 0 {
+                                ui.same_line(0.0);
+                            }
+                            ui.checkbox(im_str!("##beat{}", beat), activated);
+                        }
+                    }
+                    Partition::Midi { ref file, .. } => {
+                        ui.text(im_str!("midi not loaded: {}", file.to_string_lossy()));
+                    }
+                }
+            });
+        }
+    }
+}
+
+impl Inspect for RhythmCoupling {
+    fn inspect(&mut self, ui: &Ui, bounds: &IndexBounds) {
+        edit_index(ui, "activator", &mut self.activator, bounds.activators);
+        edit_f32(ui, "time", &mut self.time);
+        edit_clamp(ui, "coupling", &mut self.coupling);
+        edit_f32(ui, "period coupling", &mut self.period_coupling);
+        ui.text(im_str!("current time: {:.3}", self.current_time));
+    }
+}
+
+impl Inspect for SwordRifle {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_usize(ui, "sword damage", &mut self.sword_damage);
+        edit_f32(ui, "sword reload time", &mut self.sword_reload_time);
+        let (length, range) = (self.sword_length, self.sword_range);
+        edit_f32(ui, "sword length", &mut self.sword_length);
+        edit_f32(ui, "sword range", &mut self.sword_range);
+        if (length, range) != (self.sword_length, self.sword_range) {
+            self.compute_shapes();
+        }
+        edit_usize(ui, "rifle damage", &mut self.rifle_damage);
+        edit_f32(ui, "rifle reload time", &mut self.rifle_reload_time);
+    }
+}
+
+impl Inspect for PositionInPath {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_f32(ui, "velocity", &mut self.velocity);
+        ui.text(im_str!("point: {}/{}", self.current_point, self.points.len()));
+    }
+}
+
+impl Inspect for SoundEmitter {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        ui.text(im_str!("sound: {}", self.sound));
+        ui.text(im_str!("looping: {}", self.looping));
+        ui.checkbox(im_str!("doppler"), &mut self.doppler);
+    }
+}
+
+impl Inspect for ParticleEffects {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        for emitter in self.emitters() {
+            ui.text(im_str!("{}", emitter));
+        }
+    }
+}
+
+impl Inspect for CameraTarget {
+    fn inspect(&mut self, ui: &Ui, _bounds: &IndexBounds) {
+        edit_f32(ui, "look ahead", &mut self.look_ahead);
+    }
+}
+
+/// Entity whose collider contains the point
+fn entity_at(world: &World, point: ::na::Vector2<f32>) -> Option<Entity> {
+    let physic_world = world.read_resource::<::resource::PhysicWorld>();
+    let bodies_map = world.read_resource::<::resource::BodiesMap>();
+    let point = ::na::Point2::from_coordinates(point);
+    let groups = CollisionGroups::new();
+    let entity = physic_world
+        .collision_world()
+        .interferences_with_point(&point, &groups)
+        .map(|obj| obj.data().body())
+        .filter(|&body| body != BodyHandle::ground())
+        .filter_map(|body| bodies_map.get(&body).cloned())
+        .next();
+    entity
+}
+
+fn body(ui: &Ui, entity: Entity, world: &World) {
+    let bodies = world.read_storage::<RigidBody>();
+    let body = match bodies.get(entity) {
+        Some(body) => body,
+        None => return,
+    };
+    let mut physic_world = world.write_resource::<::resource::PhysicWorld>();
+    let body = body.get_mut(&mut physic_world);
+    let position = body.position().translation.vector;
+    ui.text(im_str!("position: {:.2} {:.2}", position[0], position[1]));
+    ui.text(im_str!("rotation: {:.2}", body.position().rotation.angle()));
+    ui.text(im_str!("status: {:?}", body.status()));
+
+    let velocity = *body.velocity();
+    let mut linear = [velocity.linear[0], velocity.linear[1]];
+    let mut angular = velocity.angular;
+    let changed = ui.input_float2(im_str!("linear velocity"), &mut linear).build()
+        | ui.input_float(im_str!("angular velocity"), &mut angular).build();
+    if changed {
+        body.set_velocity(Velocity2::new(::na::Vector2::new(linear[0], linear[1]), angular));
+    }
+}
+
+/// Window listing entities, editing the selected one and spawning insertables
+///
+/// Right click selects the entity under the cursor, middle click spawns the insertable
+pub fn build(ui: &Ui, world: &World) {
+    let mut inspector = world.write_resource::<Inspector>();
+    if !inspector.open {
+        return;
+    }
+    if let Some(selected) = inspector.selected {
+        if !world.entities().is_alive(selected) {
+            inspector.selected = None;
+        }
+    }
+
+    let mut insertables = world
+        .read_resource::<::resource::InsertablesMap>()
+        .keys()
+        .cloned()
+        .collect::<Vec<_>>();
+    insertables.sort();
+
+    if !ui.want_capture_mouse() {
+        let clicked_right = ui.imgui().is_mouse_clicked(ImMouseButton::Right);
+        let clicked_middle = ui.imgui().is_mouse_clicked(ImMouseButton::Middle);
+        if clicked_right || clicked_middle {
+            let (x, y) = ui.imgui().mouse_pos();
+            let size = world.read_resource::<::resource::WindowSize>().0;
+            let position = world
+                .read_resource::<::resource::Camera>()
+                .screen_to_world([x, y], [size.0, size.1]);
+            if clicked_right {
+                inspector.selected = entity_at(world, position);
+            }
+            let insertable = insertables.get(inspector.spawn as usize).and_then(|name| {
+                world
+                    .read_resource::<::resource::InsertablesMap>()
+                    .get(name)
+                    .cloned()
+            });
+            if let (true, Some(insertable)) = (clicked_middle, insertable) {
+                let position = ::na::Isometry2::new(position, 0.0);
+                inspector.selected = Some(insertable.insert(InsertPosition::from(position), world));
+            }
+        }
+    }
+
+    ui.window(im_str!("Entity inspector")).build(|| {
+        let names = insertables
+            .iter()
+            .map(|name| ImString::new(name.clone()))
+            .collect::<Vec<_>>();
+        let items = names.iter().map(|name| &**name).collect::<Vec<&ImStr>>();
+        ui.combo(im_str!("spawn"), &mut inspector.spawn, &items, 10);
+        ui.separator();
+
+        ui.tree_node(im_str!("AI debug")).build(|| {
+            let mut ai_debug = world.write_resource::<::resource::AiDebug>();
+            ui.checkbox(
+                im_str!("velocity to player memory"),
+                &mut ai_debug.velocity_to_player_memory,
+            );
+            ui.checkbox(
+                im_str!("velocity to player random"),
+                &mut ai_debug.velocity_to_player_random,
+            );
+            ui.checkbox(im_str!("position in path"), &mut ai_debug.position_in_path);
+            ui.checkbox(im_str!("boid"), &mut ai_debug.boid);
+            ui.checkbox(im_str!("turret spawner"), &mut ai_debug.turret_spawner);
+            ui.checkbox(im_str!("velocity dampings"), &mut ai_debug.velocity_dampings);
+        });
+        ui.separator();
+
+        ui.tree_node(im_str!("Entities")).build(|| {
+            let entities = {
+                let animation_states = world.read_storage::<AnimationState>();
+                (
+                    &*world.entities(),
+                    &world.read_storage::<RigidBody>(),
+                    animation_states.maybe(),
+                ).join()
+                    .map(|(entity, _, state)| (entity, state.map(|state| state.specie)))
+                    .collect::<Vec<_>>()
+            };
+            for (entity, specie) in entities {
+                let label = ImString::new(match specie {
+                    Some(specie) => format!("{} {:?}", entity.id(), specie),
+                    None => format!("{}", entity.id()),
+                });
+                let selected = inspector.selected == Some(entity);
+                if ui.selectable(&label, selected, ImGuiSelectableFlags::empty(), (0.0, 0.0)) {
+                    inspector.selected = Some(entity);
+                }
+            }
+        });
+        ui.separator();
+
+        let entity = match inspector.selected {
+            Some(entity) => entity,
+            None => {
+                ui.text(im_str!("Right click an entity to select it"));
+                return;
+            }
+        };
+        ui.text(im_str!("Entity {}", entity.id()));
+        if ui.button(im_str!("Delete"), (0.0, 0.0)) {
+            world.entities().delete(entity).unwrap();
+            inspector.selected = None;
+            return;
+        }
+        ui.tree_node(im_str!("Body")).build(|| body(ui, entity, world));
+        ::entity::MetaComponent::inspect(entity, world, ui);
+    });
+}
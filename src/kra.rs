@@ -0,0 +1,447 @@
+//! Import of animations drawn in Krita
+//!
+//! Each paint layer of `name.kra` is exported as the part `name_layer` with one image per
+//! frame of the document range, keyframes are held until the next one. Upper layers get
+//! greater layer depths. Layers named after an animation (`sword attack` for `SwordAttack`)
+//! are inserted in the table for the specie named after the file.
+
+use animation::{AnimationName, AnimationPartConf, AnimationSpecie, AnimationsConf, Framerate};
+use png::HasParameters;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+const ANIMATION_CONF_PATH: &str = "data/animation.ron";
+
+/// Attributes of the elements with the tag, Krita documents have no nested quotes
+fn elements(xml: &str, tag: &str) -> Vec<HashMap<String, String>> {
+    let start = format!("<{} ", tag);
+    xml.match_indices(&start)
+        .map(|(i, _)| {
+            let element = &xml[i + start.len()..];
+            let element = &element[..element.find('>').unwrap_or(element.len())];
+            let mut attributes = HashMap::new();
+            let mut split = element.split('"');
+            while let (Some(key), Some(value)) = (split.next(), split.next()) {
+                let key = key.trim().trim_right_matches('=').trim();
+                attributes.insert(key.to_string(), value.to_string());
+            }
+            attributes
+        })
+        .collect()
+}
+
+fn attribute<'a>(
+    attributes: &'a HashMap<String, String>,
+    key: &str,
+) -> Result<&'a str, ::failure::Error> {
+    attributes
+        .get(key)
+        .map(|value| value.as_str())
+        .ok_or_else(|| format_err!("missing attribute \"{}\"", key))
+}
+
+fn number<T: ::std::str::FromStr>(
+    attributes: &HashMap<String, String>,
+    key: &str,
+) -> Result<T, ::failure::Error> {
+    let value = attribute(attributes, key)?;
+    value
+        .parse()
+        .map_err(|_| format_err!("invalid attribute \"{}\": \"{}\"", key, value))
+}
+
+fn read_entry<R: Read + ::std::io::Seek>(
+    archive: &mut ::zip::ZipArchive<R>,
+    name: &str,
+) -> Result<Vec<u8>, ::failure::Error> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| format_err!("\"{}\": {}", name, e))?;
+    let mut bytes = vec![];
+    entry.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>, ::failure::Error> {
+    let error = || format_err!("invalid LZF data");
+    let mut output = Vec::with_capacity(len);
+    let mut i = 0;
+    while i < input.len() {
+        let control = input[i] as usize;
+        i += 1;
+        if control < 32 {
+            let run = control + 1;
+            output.extend_from_slice(input.get(i..i + run).ok_or_else(error)?);
+            i += run;
+        } else {
+            let mut run = control >> 5;
+            if run == 7 {
+                run += *input.get(i).ok_or_else(error)? as usize;
+                i += 1;
+            }
+            let back = ((control & 0x1f) << 8) + *input.get(i).ok_or_else(error)? as usize + 1;
+            i += 1;
+            if back > output.len() {
+                return Err(error());
+            }
+            let from = output.len() - back;
+            for j in 0..run + 2 {
+                let byte = output[from + j];
+                output.push(byte);
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// Line starting at the cursor, the cursor is moved after it
+fn next_line<'a>(data: &'a [u8], cursor: &mut usize) -> Result<&'a str, ::failure::Error> {
+    let end = data[*cursor..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .ok_or_else(|| format_err!("unexpected end of layer"))?;
+    let line = ::std::str::from_utf8(&data[*cursor..*cursor + end])?;
+    *cursor += end + 1;
+    Ok(line)
+}
+
+/// Draw the tiles of a layer file into the RGBA canvas
+fn draw_tiles(
+    data: &[u8],
+    default_pixel: [u8; 4],
+    offset: (i32, i32),
+    canvas: &mut Vec<u8>,
+    (width, height): (u32, u32),
+) -> Result<(), ::failure::Error> {
+    for pixel in canvas.chunks_mut(4) {
+        pixel.copy_from_slice(&[
+            default_pixel[2],
+            default_pixel[1],
+            default_pixel[0],
+            default_pixel[3],
+        ]);
+    }
+
+    let mut cursor = 0;
+    let mut header = HashMap::new();
+    loop {
+        let mut split = next_line(data, &mut cursor)?.splitn(2, ' ');
+        let key = split.next().unwrap_or("").to_string();
+        let value = split.next().unwrap_or("").to_string();
+        let done = key == "DATA";
+        header.insert(key, value);
+        if done {
+            break;
+        }
+    }
+    let value = |key: &str| -> Result<usize, ::failure::Error> {
+        header
+            .get(key)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| format_err!("invalid layer header \"{}\"", key))
+    };
+    let (tile_width, tile_height) = (value("TILEWIDTH")?, value("TILEHEIGHT")?);
+    if value("VERSION")? != 2 || value("PIXELSIZE")? != 4 {
+        return Err(format_err!("only version 2 layers of 8 bits RGBA are supported"));
+    }
+    let tiles = value("DATA")?;
+    let tile_pixels = tile_width * tile_height;
+
+    for _ in 0..tiles {
+        let tile_header = next_line(data, &mut cursor)?;
+
+        let fields = tile_header.split(',').collect::<Vec<_>>();
+        if fields.len() != 4 || fields[2] != "LZF" {
+            return Err(format_err!("invalid tile header \"{}\"", tile_header));
+        }
+        let parse = |s: &str| {
+            s.parse::<i64>()
+                .map_err(|_| format_err!("invalid tile header \"{}\"", tile_header))
+        };
+        let (x, y, size) = (parse(fields[0])?, parse(fields[1])?, parse(fields[3])? as usize);
+        let tile = data
+            .get(cursor..cursor + size)
+            .ok_or_else(|| format_err!("unexpected end of layer"))?;
+        cursor += size;
+
+        let pixels = match tile.first() {
+            Some(&1) => lzf_decompress(&tile[1..], tile_pixels * 4)?,
+            Some(&0) => tile[1..].to_vec(),
+            _ => return Err(format_err!("invalid tile compression flag")),
+        };
+        if pixels.len() != tile_pixels * 4 {
+            return Err(format_err!("invalid tile size"));
+        }
+
+        // Channels are stored one after the other in BGRA order
+        for ty in 0..tile_height {
+            for tx in 0..tile_width {
+                let cx = x + offset.0 as i64 + tx as i64;
+                let cy = y + offset.1 as i64 + ty as i64;
+                if cx < 0 || cy < 0 || cx >= width as i64 || cy >= height as i64 {
+                    continue;
+                }
+                let p = ty * tile_width + tx;
+                let c = ((cy as u32 * width + cx as u32) * 4) as usize;
+                canvas[c] = pixels[2 * tile_pixels + p];
+                canvas[c + 1] = pixels[tile_pixels + p];
+                canvas[c + 2] = pixels[p];
+                canvas[c + 3] = pixels[3 * tile_pixels + p];
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_png(path: &Path, pixels: &[u8], width: u32, height: u32) -> Result<(), ::failure::Error> {
+    let file =
+        File::create(path).map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+    let mut encoder = ::png::Encoder::new(file, width, height);
+    encoder
+        .set(::png::ColorType::RGBA)
+        .set(::png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
+
+/// `sword attack` into `sword_attack`
+fn snake_case(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// `sword attack` into `SwordAttack`
+fn camel_case(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+struct Layer {
+    name: String,
+    filename: String,
+    x: i32,
+    y: i32,
+    keyframes: Option<String>,
+}
+
+/// Export the frames of the layers in the animation directory and update the configuration
+pub fn import<P: AsRef<Path>>(path: P) -> Result<(), ::failure::Error> {
+    let path = path.as_ref();
+    let stem = path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .ok_or_else(|| format_err!("\"{}\": invalid file name", path.to_string_lossy()))?;
+    let file =
+        File::open(path).map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+    let mut archive = ::zip::ZipArchive::new(file)
+        .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+
+    let maindoc = String::from_utf8(read_entry(&mut archive, "maindoc.xml")?)?;
+    let image = elements(&maindoc, "IMAGE")
+        .into_iter()
+        .next()
+        .ok_or_else(|| format_err!("maindoc.xml: no image"))?;
+    let document = attribute(&image, "name")?.to_string();
+    let width: u32 = number(&image, "width")?;
+    let height: u32 = number(&image, "height")?;
+    let framerate = elements(&maindoc, "framerate")
+        .first()
+        .map(|framerate| number::<f32>(framerate, "value"))
+        .unwrap_or(Ok(24.0))?;
+    let (from, to) = match elements(&maindoc, "range").first() {
+        Some(range) => (number::<u32>(range, "from")?, number::<u32>(range, "to")?),
+        None => (0, 0),
+    };
+
+    let mut layers = vec![];
+    for layer in elements(&maindoc, "layer") {
+        if layer.get("nodetype").map(|t| t.as_str()) != Some("paintlayer") {
+            continue;
+        }
+        layers.push(Layer {
+            name: attribute(&layer, "name")?.to_string(),
+            filename: attribute(&layer, "filename")?.to_string(),
+            x: number(&layer, "x")?,
+            y: number(&layer, "y")?,
+            keyframes: layer.get("keyframes").cloned(),
+        });
+    }
+
+    let mut conf: AnimationsConf = ::ron::de::from_reader(File::open(ANIMATION_CONF_PATH)?)
+        .map_err(|e| format_err!("\"{}\": {}", ANIMATION_CONF_PATH, e))?;
+    let specie = ::ron::de::from_str::<AnimationSpecie>(&camel_case(&stem)).ok();
+
+    let layer_count = layers.len();
+    for (i, layer) in layers.iter().enumerate() {
+        let part_name = format!("{}_{}", stem, snake_case(&layer.name));
+        let layer_dir = format!("{}/layers/", document);
+
+        // Frame file and offset of each time of the range
+        let mut keyframes = vec![];
+        if let Some(ref keyframes_file) = layer.keyframes {
+            let keyframes_path = layer_dir.clone() + keyframes_file;
+            let xml = String::from_utf8(read_entry(&mut archive, &keyframes_path)?)?;
+            let offsets = elements(&xml, "offset");
+            for (j, keyframe) in elements(&xml, "keyframe").iter().enumerate() {
+                let offset = offsets
+                    .get(j)
+                    .map(|offset| -> Result<_, ::failure::Error> {
+                        Ok((number::<i32>(offset, "x")?, number::<i32>(offset, "y")?))
+                    })
+                    .unwrap_or(Ok((0, 0)))?;
+                keyframes.push((
+                    number::<u32>(keyframe, "time")?,
+                    attribute(keyframe, "frame")?.to_string(),
+                    offset,
+                ));
+            }
+            keyframes.sort_by_key(|&(time, _, _)| time);
+        }
+        let frames = if keyframes.is_empty() {
+            vec![(layer.filename.clone(), (0, 0))]
+        } else {
+            (from..to + 1)
+                .map(|time| {
+                    let &(_, ref frame, offset) = keyframes
+                        .iter()
+                        .rev()
+                        .find(|&&(t, _, _)| t <= time)
+                        .unwrap_or(&keyframes[0]);
+                    (frame.clone(), offset)
+                })
+                .collect()
+        };
+
+        // Remove the images of a previous import
+        for entry in fs::read_dir(&conf.directory)? {
+            let entry = entry?.path();
+            let previous = entry
+                .file_stem()
+                .map(|stem| {
+                    let stem = stem.to_string_lossy();
+                    stem.len() == part_name.len() + 4 && stem.starts_with(&part_name)
+                        && stem[part_name.len()..].chars().all(|c| c.is_digit(10))
+                })
+                .unwrap_or(false);
+            if previous && entry.extension().map(|e| e == "png").unwrap_or(false) {
+                fs::remove_file(entry)?;
+            }
+        }
+
+        let mut canvas = vec![0; (width * height * 4) as usize];
+        for (frame_number, (frame, offset)) in frames.iter().enumerate() {
+            let data = read_entry(&mut archive, &(layer_dir.clone() + frame))?;
+            let default_pixel_path = layer_dir.clone() + frame + ".defaultpixel";
+            let default_pixel = read_entry(&mut archive, &default_pixel_path)
+                .ok()
+                .and_then(|pixel| {
+                    if pixel.len() == 4 {
+                        Some([pixel[0], pixel[1], pixel[2], pixel[3]])
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or([0; 4]);
+            draw_tiles(
+                &data,
+                default_pixel,
+                (layer.x + offset.0, layer.y + offset.1),
+                &mut canvas,
+                (width, height),
+            ).map_err(|e| format_err!("layer \"{}\" frame \"{}\": {}", layer.name, frame, e))?;
+            write_png(
+                &conf.directory
+                    .join(format!("{}{:04}.png", part_name, frame_number)),
+                &canvas,
+                width,
+                height,
+            )?;
+        }
+
+        let events = conf.parts
+            .remove(&part_name)
+            .map(|part| part.events)
+            .unwrap_or_default();
+        conf.parts.insert(
+            part_name.clone(),
+            AnimationPartConf {
+                filename: part_name.clone(),
+                layer: (layer_count - i) as f32 / (layer_count + 1) as f32,
+                framerate: Framerate::Fix(framerate),
+                events,
+            },
+        );
+
+        let name = ::ron::de::from_str::<AnimationName>(&camel_case(&layer.name)).ok();
+        if let (Some(specie), Some(name)) = (specie, name) {
+            conf.table.insert((specie, name), vec![part_name]);
+        }
+    }
+
+    let ron = ::ron::ser::to_string_pretty(&conf, Default::default())?;
+    fs::write(ANIMATION_CONF_PATH, ron)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_attributes() {
+        let xml = r#"<layers>
+   <layer name="sword attack" x="0" filename="layer4"/>
+   <layer filename="layer3" visible="0" nodetype="paintlayer">
+  </layers>"#;
+        let layers = elements(xml, "layer");
+        assert_eq!(layers.len(), 2);
+        assert_eq!(layers[0].len(), 3);
+        assert_eq!(layers[0]["name"], "sword attack");
+        assert_eq!(layers[0]["x"], "0");
+        assert_eq!(layers[0]["filename"], "layer4");
+        assert_eq!(layers[1].len(), 3);
+        assert_eq!(layers[1]["nodetype"], "paintlayer");
+        assert!(elements(xml, "lay").is_empty());
+    }
+
+    #[test]
+    fn lzf_literals_and_back_references() {
+        assert_eq!(lzf_decompress(&[0x02, b'a', b'b', b'c'], 3).unwrap(), b"abc");
+        assert_eq!(
+            lzf_decompress(&[0x02, b'a', b'b', b'c', 0x20, 0x02], 6).unwrap(),
+            b"abcabc"
+        );
+        // Back reference overlapping the bytes it copies
+        assert_eq!(lzf_decompress(&[0x00, b'a', 0x60, 0x00], 6).unwrap(), b"aaaaaa");
+        // Length of 7 continued on the next byte
+        assert_eq!(
+            lzf_decompress(&[0x00, b'a', 0xe0, 0x01, 0x00], 11).unwrap(),
+            vec![b'a'; 11]
+        );
+    }
+
+    #[test]
+    fn lzf_invalid_data() {
+        assert!(lzf_decompress(&[0x02, b'a'], 3).is_err());
+        assert!(lzf_decompress(&[0x20, 0x00], 3).is_err());
+        assert!(lzf_decompress(&[0x00, b'a', 0x20], 3).is_err());
+        assert!(lzf_decompress(&[0x00, b'a', 0xe0], 3).is_err());
+    }
+}
@@ -33,11 +33,9 @@ extern crate winit;
 extern crate specs_derive;
 extern crate rodio;
 extern crate show_message;
-extern crate heck;
-extern crate enum_iterator;
-#[macro_use]
-extern crate enum_iterator_derive;
 extern crate app_dirs2;
+extern crate rusttype;
+extern crate zip;
 
 mod dynamic_mixer;
 mod audio;
@@ -46,11 +44,22 @@ pub mod component;
 mod config_menu;
 pub mod entity;
 mod force_generator;
+mod inspector;
 pub mod map;
+mod midi;
+mod music;
 mod resource;
 mod system;
 #[macro_use]
 mod util;
+mod visibility;
+mod atlas;
+mod kra;
+mod map_geometry;
+mod particle;
+mod render;
+mod text;
+mod hud;
 mod game_state;
 mod graphics;
 mod retained_storage;
@@ -59,10 +68,12 @@ pub use resource::Conf;
 pub use util::ClampFunction;
 
 use game_state::GameState;
+use show_message::UnwrapOrShow;
 use specs::{DispatcherBuilder, World};
 use std::thread;
 use std::time::Duration;
 use std::time::Instant;
+use std::time::{SystemTime, UNIX_EPOCH};
 use vulkano::instance::Instance;
 use vulkano_win::VkSurfaceBuild;
 use winit::CursorState;
@@ -70,6 +81,36 @@ use winit::CursorState;
 const CMP_PRECISION: f32 = 100.0;
 
 fn main() {
+    // Import Krita animations instead of running the game: `fefe import-kra FILE...`
+    let args = ::std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(|arg| arg == "import-kra").unwrap_or(false) {
+        for path in &args[2..] {
+            if let Err(e) = ::kra::import(path) {
+                eprintln!("Failed to import \"{}\": {}", path, e);
+                ::std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Capture a map without window: `fefe capture-map MAP WIDTH HEIGHT FILE`
+    if args.get(1).map(|arg| arg == "capture-map").unwrap_or(false) {
+        let result = match (args.get(2), args.get(3), args.get(4), args.get(5)) {
+            (Some(map), Some(width), Some(height), Some(path)) => width
+                .parse::<u32>()
+                .and_then(|width| height.parse::<u32>().map(|height| [width, height]))
+                .map_err(|e| format_err!("invalid dimensions: {}", e))
+                .and_then(|dimensions| ::render::SoftwareBackend::capture_map(map, dimensions))
+                .and_then(|capture| capture.save(path)),
+            _ => Err(format_err!("usage: capture-map MAP WIDTH HEIGHT FILE")),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to capture map: {}", e);
+            ::std::process::exit(1);
+        }
+        return;
+    }
+
     ::std::env::set_var("WINIT_UNIX_BACKEND", "x11");
 
     let mut gilrs = gilrs::Gilrs::new().unwrap();
@@ -93,54 +134,17 @@ fn main() {
     let mut imgui = ::util::init_imgui();
     let mut graphics = graphics::Graphics::new(&window, &mut imgui);
 
-    let mut world = World::new();
-    world.register::<::component::RigidBody>();
-    world.register::<::component::AnimationState>();
-    world.register::<::component::Ground>();
-    world.register::<::component::Life>();
-    world.register::<::component::Aim>();
-    world.register::<::component::Player>();
-    world.register::<::component::GravityToPlayers>();
-    world.register::<::component::DeadOnContact>();
-    world.register::<::component::ContactDamage>();
-    world.register::<::component::Contactor>();
-    world.register::<::component::ControlForce>();
-    world.register::<::component::PlayersAimDamping>();
-    world.register::<::component::PlayersDistanceDamping>();
-    world.register::<::component::GravityToPlayers>();
-    world.register::<::component::Damping>();
-    world.register::<::component::TurretSpawner>();
-    world.register::<::component::DebugColor>();
-    world.register::<::component::UniqueSpawner>();
-    world.register::<::component::ChamanSpawner>();
-    world.register::<::component::DebugCircles>();
-    world.register::<::component::DebugRays>();
-    world.register::<::component::VelocityToPlayerMemory>();
-    world.register::<::component::VelocityToPlayerRandom>();
-    world.register::<::component::VelocityToPlayerCircle>();
-    world.register::<::component::VelocityControl>();
-    world.register::<::component::VelocityAimDamping>();
-    world.register::<::component::VelocityDistanceDamping>();
-    world.register::<::component::PositionInPath>();
-    world.register::<::component::VelocityToPlayerCircle>();
-    world.register::<::component::Activators>();
-    world.register::<::component::Boid>();
-    world.register::<::component::SwordRifle>();
-
     let conf = ::resource::Conf::load();
     let save = ::resource::Save::load();
-    world.add_resource(::resource::Audio::init(&conf, &save));
-    world.add_resource(::resource::UpdateTime(0.0));
-    world.add_resource(::resource::AnimationImages(vec![]));
-    world.add_resource(::resource::Camera::new(::na::one(), conf.zoom));
-    world.add_resource(::resource::DebugShapes(vec![]));
+    let audio = ::resource::Audio::init(&conf, &save);
+    let mut world = World::new();
+    init_world(&mut world, conf, save, audio);
     world.add_resource(::resource::WindowSize(
         window.window().get_inner_size().unwrap(),
     ));
     world.add_resource(imgui);
-    world.add_resource(conf);
-    world.add_resource(save);
-    world.add_resource(::resource::Tempos(vec![::resource::Tempo::new(0.2)]));
+    world.add_resource(::resource::ConfigMenu::new());
+    world.add_resource(::resource::Inspector::new());
     world.maintain();
 
     // TOCHECK:
@@ -151,6 +155,8 @@ fn main() {
     let mut update_dispatcher = DispatcherBuilder::new()
         .with(::system::ActivatorSystem, "activator", &[])
         .with(::system::PhysicSystem::new(), "physic", &[])
+        .with(::system::RhythmCouplingSystem, "rhythm coupling", &["activator", "physic"])
+        .with(::system::VisibilitySystem, "visibility", &["physic"])
         .with_barrier()
         .with(::system::PositionInPathSystem, "position in path", &[])
         .with_barrier()
@@ -172,11 +178,19 @@ fn main() {
              "velocity control",
              "boid",
         ])
-        .with(::system::LifeSystem, "life", &[])
+        // Before life so that killing blows are shown
+        .with(::system::DamageTextSystem::new(), "damage text", &[])
+        .with(::system::LifeSystem, "life", &["damage text"])
         .with_barrier() // Draw barrier
         .with(::system::AudioSystem, "audio", &[])
+        .with(::system::MusicSystem, "music", &[])
+        .with(::system::SoundEmitterSystem, "sound emitter", &["audio"])
+        .with(::system::ParticleSystem, "particle", &[])
+        .with(::system::HudSystem::new(), "hud", &[])
+        .with(::system::MinimapSystem, "minimap", &["hud"])
         .with(::system::AnimationSystem, "animation", &[])
-        .with(::system::CameraSystem, "camera", &[])
+        .with(::system::AnimationEventSystem, "animation event", &["animation"])
+        .with(::system::CameraSystem::new(), "camera", &[])
         .build();
 
     let mut fps_counter = fps_counter::FPSCounter::new();
@@ -186,8 +200,10 @@ fn main() {
     let mut game_state = Box::new(game_state::Game::default()) as Box<GameState>;
 
     let mut mouse_down = [false; 5];
+    let mut capture = false;
 
-    ::map::load_map("one".into(), &mut world).unwrap();
+    ::map::load_map("one".into(), &mut world)
+        .unwrap_or_else_show(|e| format!("Failed to load map: {}", e));
 
     'main_loop: loop {
         // Parse events
@@ -223,6 +239,37 @@ fn main() {
                 } => {
                     break 'main_loop;
                 }
+                winit::Event::WindowEvent {
+                    event:
+                        winit::WindowEvent::KeyboardInput {
+                            input:
+                                winit::KeyboardInput {
+                                    state: winit::ElementState::Pressed,
+                                    virtual_keycode: Some(winit::VirtualKeyCode::F12),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    capture = true;
+                }
+                winit::Event::WindowEvent {
+                    event:
+                        winit::WindowEvent::KeyboardInput {
+                            input:
+                                winit::KeyboardInput {
+                                    state: winit::ElementState::Pressed,
+                                    virtual_keycode: Some(winit::VirtualKeyCode::F1),
+                                    ..
+                                },
+                            ..
+                        },
+                    ..
+                } => {
+                    let mut inspector = world.write_resource::<::resource::Inspector>();
+                    inspector.open = !inspector.open;
+                }
                 _ => (),
             }
             ::util::send_event_to_imgui(&ev, &mut world.write_resource(), &mut mouse_down);
@@ -249,6 +296,10 @@ fn main() {
             .saturating_mul(1_000_000_000)
             .saturating_add(delta_time.subsec_nanos() as u64)
             as f32 / 1_000_000_000.0;
+        {
+            let update_time = world.read_resource::<::resource::UpdateTime>().0;
+            world.write_resource::<::resource::Audio>().advance_clock(update_time);
+        }
 
         update_dispatcher.dispatch(&mut world.res);
         game_state = game_state.update_draw_ui(&mut world);
@@ -256,6 +307,21 @@ fn main() {
         // Maintain world
         ::util::safe_maintain(&mut world);
 
+        // Capture the frame with the software backend
+        if capture {
+            capture = false;
+            let size = world.read_resource::<::resource::WindowSize>().0;
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0);
+            let path = format!("capture_{}.png", timestamp);
+            let frame = ::render::SoftwareBackend::capture(&world, [size.0, size.1]);
+            if let Err(e) = frame.save(&path) {
+                eprintln!("Failed to capture \"{}\": {}", path, e);
+            }
+        }
+
         // Draw
         graphics.draw(&mut world, &window);
 
@@ -272,3 +338,70 @@ fn main() {
         fps_counter.tick();
     }
 }
+
+/// Register the components and add the resources not depending on the window
+fn init_world(
+    world: &mut World,
+    conf: ::resource::Conf,
+    save: ::resource::Save,
+    audio: ::resource::Audio,
+) {
+    world.register::<::component::RigidBody>();
+    world.register::<::component::AnimationState>();
+    world.register::<::component::Ground>();
+    world.register::<::component::Life>();
+    world.register::<::component::Aim>();
+    world.register::<::component::Player>();
+    world.register::<::component::GravityToPlayers>();
+    world.register::<::component::DeadOnContact>();
+    world.register::<::component::ContactDamage>();
+    world.register::<::component::Contactor>();
+    world.register::<::component::ControlForce>();
+    world.register::<::component::PlayersAimDamping>();
+    world.register::<::component::PlayersDistanceDamping>();
+    world.register::<::component::GravityToPlayers>();
+    world.register::<::component::Damping>();
+    world.register::<::component::TurretSpawner>();
+    world.register::<::component::DebugColor>();
+    world.register::<::component::UniqueSpawner>();
+    world.register::<::component::ChamanSpawner>();
+    world.register::<::component::DebugCircles>();
+    world.register::<::component::DebugRays>();
+    world.register::<::component::VelocityToPlayerMemory>();
+    world.register::<::component::VelocityToPlayerRandom>();
+    world.register::<::component::VelocityToPlayerCircle>();
+    world.register::<::component::VelocityControl>();
+    world.register::<::component::VelocityAimDamping>();
+    world.register::<::component::VelocityDistanceDamping>();
+    world.register::<::component::PositionInPath>();
+    world.register::<::component::VelocityToPlayerCircle>();
+    world.register::<::component::Activators>();
+    world.register::<::component::RhythmCoupling>();
+    world.register::<::component::Boid>();
+    world.register::<::component::SwordRifle>();
+    world.register::<::component::SoundEmitter>();
+    world.register::<::component::Visible>();
+    world.register::<::component::ParticleEffects>();
+    world.register::<::component::CameraTarget>();
+    world.register::<::component::Revealed>();
+
+    world.add_resource(::resource::UpdateTime(0.0));
+    world.add_resource(::resource::AnimationImages(vec![]));
+    world.add_resource(::resource::DeathAnimations(vec![]));
+    world.add_resource(::resource::AnimationEvents(vec![]));
+    world.add_resource(::resource::Camera::new(::na::one(), conf.zoom));
+    world.add_resource(::resource::CameraZones(vec![]));
+    world.add_resource(::resource::Tempos(
+        conf.tempos.iter().map(|&time| ::resource::Tempo::new(time)).collect(),
+    ));
+    world.add_resource(::resource::DebugShapes(vec![]));
+    world.add_resource(::resource::AiDebug::default());
+    world.add_resource(::resource::Texts(vec![]));
+    world.add_resource(::resource::HudShapes(vec![]));
+    world.add_resource(::resource::Visibility(None));
+    world.add_resource(::resource::MapGeometry::new());
+    world.add_resource(::resource::Particles::init(&conf));
+    world.add_resource(audio);
+    world.add_resource(conf);
+    world.add_resource(save);
+}
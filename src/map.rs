@@ -1,4 +1,4 @@
-use entity::{FillableObject, InsertableObject, SegmentableObject};
+use entity::{FillableObject, InsertableObject, MetaComponent, SegmentableObject};
 use lyon::svg::parser::svg::ElementEnd::Close;
 use lyon::svg::parser::svg::ElementEnd::Empty;
 use lyon::svg::parser::svg::Name::Svg;
@@ -6,6 +6,8 @@ use lyon::svg::parser::svg::{Token, Tokenizer};
 use lyon::svg::parser::FromSpan;
 use lyon::svg::parser::{AttributeId, ElementId};
 use lyon::svg::path::default::Path;
+use lyon::svg::path::iterator::PathIterator;
+use lyon::svg::path::FlattenedEvent;
 use rand::distributions::{Distribution, Weighted, WeightedChoice};
 use rand::{thread_rng, Rng};
 use specs::World;
@@ -58,12 +60,19 @@ pub(crate) fn load_map(name: String, world: &mut World) -> Result<(), ::failure:
         .map(|_| vec![])
         .collect::<Vec<_>>();
 
+    let mut map_geometry = ::resource::MapGeometry::new();
+    map_geometry.generation = world.read_resource::<::resource::MapGeometry>().generation + 1;
+
+    let mut camera_zones = vec![];
+
     let mut tokenizer = Tokenizer::from_str(&svg_string);
 
     let mut in_marker = false;
     let mut style = None;
     let mut d = None;
     let mut in_path_attribute = false;
+    let mut in_rect_attribute = false;
+    let mut rect = [None; 4];
     while let Some(token) = tokenizer.next() {
         let token = token.map_err(|e| format_err!("\"{}\": {}", svg_path.to_string_lossy(), e))?;
 
@@ -93,19 +102,7 @@ pub(crate) fn load_map(name: String, world: &mut World) -> Result<(), ::failure:
                             .zip(insert_rules_entities.iter_mut())
                         {
                             if style.to_str().contains(&rule.trigger) {
-                                let svg_builder = Path::builder().with_svg();
-                                let commands = d.to_str();
-                                let path =
-                                    ::lyon::svg::path_utils::build_path(svg_builder, commands)
-                                        .map_err(|e| {
-                                            format_err!(
-                                                "\"{}\": invalid path \"{}\": {:?}",
-                                                svg_path.to_string_lossy(),
-                                                commands,
-                                                e
-                                            )
-                                        })?;
-                                insert_rule_entities.push(path);
+                                insert_rule_entities.push(parse_path(d.to_str(), &svg_path)?);
                             }
                         }
 
@@ -116,19 +113,7 @@ pub(crate) fn load_map(name: String, world: &mut World) -> Result<(), ::failure:
                             .zip(fill_rules_entities.iter_mut())
                         {
                             if style.to_str().contains(&rule.trigger) {
-                                let svg_builder = Path::builder().with_svg();
-                                let commands = d.to_str();
-                                let path =
-                                    ::lyon::svg::path_utils::build_path(svg_builder, commands)
-                                        .map_err(|e| {
-                                            format_err!(
-                                                "\"{}\": invalid path \"{}\": {:?}",
-                                                svg_path.to_string_lossy(),
-                                                commands,
-                                                e
-                                            )
-                                        })?;
-                                fill_rule_entities.push(path);
+                                fill_rule_entities.push(parse_path(d.to_str(), &svg_path)?);
                             }
                         }
 
@@ -139,19 +124,62 @@ pub(crate) fn load_map(name: String, world: &mut World) -> Result<(), ::failure:
                             .zip(segment_rules_entities.iter_mut())
                         {
                             if style.to_str().contains(&rule.trigger) {
-                                let svg_builder = Path::builder().with_svg();
-                                let commands = d.to_str();
-                                let path =
-                                    ::lyon::svg::path_utils::build_path(svg_builder, commands)
-                                        .map_err(|e| {
-                                            format_err!(
-                                                "\"{}\": invalid path \"{}\": {:?}",
-                                                svg_path.to_string_lossy(),
-                                                commands,
-                                                e
-                                            )
-                                        })?;
-                                segment_rules_entities.push(path);
+                                segment_rules_entities.push(parse_path(d.to_str(), &svg_path)?);
+                            }
+                        }
+
+                        // Map geometry: fill rules as background and segment rules as walls
+                        let path_style = ::map_geometry::PathStyle::parse(style.to_str());
+                        let fill_color = settings
+                            .fill_rules
+                            .iter()
+                            .find(|rule| style.to_str().contains(&rule.trigger))
+                            .and_then(|rule| {
+                                settings
+                                    .palette
+                                    .get(&rule.trigger)
+                                    .map(|&(r, g, b, a)| [r, g, b, a])
+                                    .or(path_style.fill)
+                            });
+                        let stroke_color = settings
+                            .segment_rules
+                            .iter()
+                            .find(|rule| style.to_str().contains(&rule.trigger))
+                            .and_then(|rule| {
+                                settings
+                                    .palette
+                                    .get(&rule.trigger)
+                                    .map(|&(r, g, b, a)| [r, g, b, a])
+                                    .or(path_style.stroke)
+                            });
+                        // Camera zones from the bounds of paths
+                        let camera_zone_mode = settings
+                            .camera_zone_rules
+                            .iter()
+                            .find(|rule| style.to_str().contains(&rule.trigger))
+                            .map(|rule| rule.mode);
+                        if let Some(mode) = camera_zone_mode {
+                            let path = parse_path(d.to_str(), &svg_path)?;
+                            if let Some((min, max)) = path_bounds(&path) {
+                                camera_zones.push(::graphics::CameraZone { min, max, mode });
+                            }
+                        }
+
+                        if fill_color.is_some() || stroke_color.is_some() {
+                            let path = parse_path(d.to_str(), &svg_path)?;
+                            if let Some(color) = fill_color {
+                                map_geometry.background.extend(
+                                    ::map_geometry::fill_vertices(&path, color).map_err(|e| {
+                                        format_err!("\"{}\": {}", svg_path.to_string_lossy(), e)
+                                    })?,
+                                );
+                            }
+                            if let Some(color) = stroke_color {
+                                map_geometry.walls.extend(::map_geometry::stroke_vertices(
+                                    &path,
+                                    color,
+                                    path_style.stroke_width,
+                                ));
                             }
                         }
                     }
@@ -159,6 +187,49 @@ pub(crate) fn load_map(name: String, world: &mut World) -> Result<(), ::failure:
                 }
                 _ => (),
             }
+        // Process rect attribute, only used for camera zones
+        } else if in_rect_attribute {
+            match token {
+                Token::Attribute(Svg(AttributeId::Style), value) => {
+                    style = Some(value);
+                }
+                Token::Attribute(Svg(id), value) => {
+                    let index = match id {
+                        AttributeId::X => Some(0),
+                        AttributeId::Y => Some(1),
+                        AttributeId::Width => Some(2),
+                        AttributeId::Height => Some(3),
+                        _ => None,
+                    };
+                    if let Some(index) = index {
+                        rect[index] = value
+                            .to_str()
+                            .trim_right_matches(char::is_alphabetic)
+                            .parse::<f32>()
+                            .ok();
+                    }
+                }
+                Token::ElementEnd(Empty) => {
+                    if let (Some(style), [Some(x), Some(y), Some(width), Some(height)]) =
+                        (style, rect)
+                    {
+                        let camera_zone_mode = settings
+                            .camera_zone_rules
+                            .iter()
+                            .find(|rule| style.to_str().contains(&rule.trigger))
+                            .map(|rule| rule.mode);
+                        if let Some(mode) = camera_zone_mode {
+                            camera_zones.push(::graphics::CameraZone {
+                                min: ::na::Vector2::new(x, y),
+                                max: ::na::Vector2::new(x + width, y + height),
+                                mode,
+                            });
+                        }
+                    }
+                    in_rect_attribute = false;
+                }
+                _ => (),
+            }
         } else {
             match token {
                 Token::ElementStart(Svg(ElementId::Marker)) => {
@@ -169,19 +240,115 @@ pub(crate) fn load_map(name: String, world: &mut World) -> Result<(), ::failure:
                     style = None;
                     d = None;
                 }
+                Token::ElementStart(Svg(ElementId::Rect)) => {
+                    in_rect_attribute = true;
+                    style = None;
+                    rect = [None; 4];
+                }
                 _ => (),
             }
         }
     }
 
+    world.add_resource(map_geometry);
+    world.add_resource(::resource::CameraZones(camera_zones));
+
     // Insert entities to world
     let mut insertables = world
         .read_resource::<::resource::Conf>()
         .insertables
         .clone();
     insertables.extend(settings.insertables);
+    world
+        .read_resource::<::resource::Audio>()
+        .sound_bank()
+        .check(&insertables)
+        .map_err(|e| format_err!("\"{}\": {}", settings_path.to_string_lossy(), e))?;
+    world
+        .read_resource::<::resource::Particles>()
+        .bank()
+        .check(&insertables)
+        .map_err(|e| format_err!("\"{}\": {}", settings_path.to_string_lossy(), e))?;
+    world
+        .read_resource::<::resource::Conf>()
+        .check_palettes(&insertables)
+        .map_err(|e| format_err!("\"{}\": {}", settings_path.to_string_lossy(), e))?;
+    world
+        .read_resource::<::resource::Conf>()
+        .check_tempos(&insertables)
+        .map_err(|e| format_err!("\"{}\": {}", settings_path.to_string_lossy(), e))?;
+    ::animation::ANIMATIONS
+        .check_spawns(&insertables)
+        .map_err(|e| format_err!("\"{}\": {}", settings_path.to_string_lossy(), e))?;
+
+    // Load MIDI partitions and their tempos
+    let mut tempos = ::resource::Tempos(
+        world
+            .read_resource::<::resource::Conf>()
+            .tempos
+            .iter()
+            .map(|&time| ::resource::Tempo::new(time))
+            .collect(),
+    );
+    let mut midi_tempos = HashMap::new();
+    for (name, insertable) in insertables.iter_mut() {
+        for component in insertable.components_mut() {
+            if let MetaComponent::Activators(ref mut activators) = *component {
+                for activator in activators.iter_mut() {
+                    activator
+                        .load_midi(&mut midi_tempos, &mut tempos)
+                        .map_err(|e| format_err!("insertable \"{}\": {}", name, e))?;
+                    if activator.beats().is_empty() {
+                        return Err(format_err!(
+                            "insertable \"{}\": activator partition is empty",
+                            name
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    for (name, insertable) in insertables.iter() {
+        let components = insertable.all_components(&insertables);
+        let activators_len = components
+            .iter()
+            .filter_map(|component| match **component {
+                MetaComponent::Activators(ref activators) => Some(activators.len()),
+                _ => None,
+            })
+            .last()
+            .unwrap_or(0);
+        for component in &components {
+            if let MetaComponent::RhythmCoupling(ref coupling) = **component {
+                if coupling.activator >= activators_len {
+                    return Err(format_err!(
+                        "insertable \"{}\": rhythm coupling activator {} does not exist, \
+                         entity has {} activators",
+                        name,
+                        coupling.activator,
+                        activators_len
+                    ));
+                }
+                if coupling.time <= 0.0 {
+                    return Err(format_err!(
+                        "insertable \"{}\": rhythm coupling time must be positive",
+                        name
+                    ));
+                }
+            }
+        }
+    }
+    world.add_resource(tempos);
     world.add_resource(insertables);
 
+    let sample_rate = world.read_resource::<::resource::Audio>().sample_rate();
+    let music = ::resource::Music::load(settings.music, sample_rate)
+        .map_err(|e| format_err!("\"{}\": {}", settings_path.to_string_lossy(), e))?;
+    world.add_resource(music);
+    world
+        .read_resource::<::resource::Audio>()
+        .set_reverb(settings.reverb.as_ref());
+
     for (insert_rule, insert_rule_entities) in settings
         .insert_rules
         .drain(..)
@@ -262,13 +429,12 @@ pub(crate) fn load_map(name: String, world: &mut World) -> Result<(), ::failure:
         let physic_world = world.read_resource::<::resource::PhysicWorld>();
         let mut audio = world.write_resource::<::resource::Audio>();
 
-        let position = (&players, &bodies).join().next().map(|(_, body)| {
-            body.get(&physic_world)
-                .position()
-                .translation.vector
+        let listener = (&players, &bodies).join().next().map(|(_, body)| {
+            let body = body.get(&physic_world);
+            (body.position().translation.vector, body.velocity().linear)
         });
 
-        audio.update(position, &save);
+        audio.update(listener, &save);
     }
 
     Ok(())
@@ -284,6 +450,56 @@ pub struct MapSettings {
     pub insertables: HashMap<String, InsertableObject>,
     pub fillables: HashMap<String, FillableObject>,
     pub segmentables: HashMap<String, SegmentableObject>,
+
+    #[serde(default)]
+    pub music: Option<::music::MusicSettings>,
+    #[serde(default)]
+    pub reverb: Option<::audio::Reverb>,
+    /// Colors of the map geometry by rule trigger, overriding the SVG fill and stroke
+    #[serde(default)]
+    pub palette: HashMap<String, (f32, f32, f32, f32)>,
+    /// Paths and rectangles constraining the camera
+    #[serde(default)]
+    pub camera_zone_rules: Vec<CameraZoneRule>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraZoneRule {
+    pub trigger: String,
+    pub mode: ::graphics::CameraZoneMode,
+}
+
+fn parse_path(commands: &str, svg_path: &::std::path::Path) -> Result<Path, ::failure::Error> {
+    ::lyon::svg::path_utils::build_path(Path::builder().with_svg(), commands).map_err(|e| {
+        format_err!(
+            "\"{}\": invalid path \"{}\": {:?}",
+            svg_path.to_string_lossy(),
+            commands,
+            e
+        )
+    })
+}
+
+const CAMERA_ZONE_FLATTENED_TOLERANCE: f32 = 1.0;
+
+/// Axis aligned bounds of the path
+fn path_bounds(path: &Path) -> Option<(::na::Vector2<f32>, ::na::Vector2<f32>)> {
+    path.path_iter()
+        .flattened(CAMERA_ZONE_FLATTENED_TOLERANCE)
+        .filter_map(|event| match event {
+            FlattenedEvent::MoveTo(p) | FlattenedEvent::LineTo(p) => {
+                Some(::na::Vector2::new(p.x, p.y))
+            }
+            FlattenedEvent::Close => None,
+        })
+        .fold(None, |bounds, p| match bounds {
+            None => Some((p, p)),
+            Some((min, max)) => Some((
+                ::na::Vector2::new(min[0].min(p[0]), min[1].min(p[1])),
+                ::na::Vector2::new(max[0].max(p[0]), max[1].max(p[1])),
+            )),
+        })
 }
 
 #[derive(Deserialize)]
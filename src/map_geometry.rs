@@ -0,0 +1,120 @@
+use lyon::svg::path::default::Path;
+use lyon::tessellation::geometry_builder::simple_builder;
+use lyon::tessellation::{FillOptions, FillTessellator, FillVertex, StrokeOptions,
+                         StrokeTessellator, StrokeVertex, VertexBuffers};
+
+const TESSELLATION_TOLERANCE: f32 = 0.1;
+
+/// Triangles of the map drawn below the entities, set at map load
+pub struct MapGeometry {
+    /// Vertices of the fill rules paths
+    pub background: Vec<MapVertex>,
+    /// Vertices of the segment rules paths
+    pub walls: Vec<MapVertex>,
+    /// Incremented on each load so that vertex buffers are rebuilt
+    pub generation: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct MapVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+impl MapGeometry {
+    pub fn new() -> Self {
+        MapGeometry {
+            background: vec![],
+            walls: vec![],
+            generation: 0,
+        }
+    }
+}
+
+/// Fill and stroke of an SVG style attribute, none if absent
+pub struct PathStyle {
+    pub fill: Option<[f32; 4]>,
+    pub stroke: Option<[f32; 4]>,
+    pub stroke_width: f32,
+}
+
+/// Parse `#rrggbb` colors, other notations are considered absent
+fn parse_color(value: &str, opacity: f32) -> Option<[f32; 4]> {
+    let value = value.trim();
+    if !value.starts_with('#') || value.len() != 7 {
+        return None;
+    }
+    let channel = |i: usize| {
+        u8::from_str_radix(&value[i..i + 2], 16)
+            .ok()
+            .map(|c| c as f32 / 255.0)
+    };
+    Some([channel(1)?, channel(3)?, channel(5)?, opacity])
+}
+
+impl PathStyle {
+    pub fn parse(style: &str) -> Self {
+        let property = |name: &str| {
+            style
+                .split(';')
+                .filter_map(|declaration| {
+                    let mut split = declaration.splitn(2, ':');
+                    match (split.next(), split.next()) {
+                        (Some(key), Some(value)) if key.trim() == name => Some(value.trim()),
+                        _ => None,
+                    }
+                })
+                .next()
+        };
+        let number = |name: &str, default: f32| {
+            property(name)
+                .and_then(|value| value.trim_right_matches(char::is_alphabetic).parse::<f32>().ok())
+                .unwrap_or(default)
+        };
+
+        let opacity = number("opacity", 1.0);
+        PathStyle {
+            fill: property("fill")
+                .and_then(|fill| parse_color(fill, opacity * number("fill-opacity", 1.0))),
+            stroke: property("stroke")
+                .and_then(|stroke| parse_color(stroke, opacity * number("stroke-opacity", 1.0))),
+            stroke_width: number("stroke-width", 1.0),
+        }
+    }
+}
+
+fn vertices<V, F>(buffers: VertexBuffers<V, u16>, color: [f32; 4], position: F) -> Vec<MapVertex>
+where
+    F: Fn(&V) -> [f32; 2],
+{
+    buffers
+        .indices
+        .iter()
+        .map(|&i| MapVertex {
+            position: position(&buffers.vertices[i as usize]),
+            color,
+        })
+        .collect()
+}
+
+pub fn fill_vertices(path: &Path, color: [f32; 4]) -> Result<Vec<MapVertex>, ::failure::Error> {
+    let mut buffers: VertexBuffers<FillVertex, u16> = VertexBuffers::new();
+    FillTessellator::new()
+        .tessellate_path(
+            path.path_iter(),
+            &FillOptions::tolerance(TESSELLATION_TOLERANCE).with_normals(false),
+            &mut simple_builder(&mut buffers),
+        )
+        .map_err(|e| format_err!("failed to tessellate path: {:?}", e))?;
+    Ok(vertices(buffers, color, |v| [v.position.x, v.position.y]))
+}
+
+pub fn stroke_vertices(path: &Path, color: [f32; 4], width: f32) -> Vec<MapVertex> {
+    let mut buffers: VertexBuffers<StrokeVertex, u16> = VertexBuffers::new();
+    StrokeTessellator::new().tessellate_path(
+        path.path_iter(),
+        &StrokeOptions::tolerance(TESSELLATION_TOLERANCE).with_line_width(width),
+        &mut simple_builder(&mut buffers),
+    );
+    vertices(buffers, color, |v| [v.position.x, v.position.y])
+}
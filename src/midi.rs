@@ -0,0 +1,341 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Number of partition steps in a quarter note: sixteenth notes
+pub const STEPS_PER_QUARTER: u32 = 4;
+
+/// Default tempo of a standard midi file: 120 bpm
+const DEFAULT_MICROSECONDS_PER_QUARTER: u32 = 500_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum EventKind {
+    NoteOn(u8),
+    Tempo(u32),
+    EndOfTrack,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Event {
+    /// Absolute time in ticks
+    tick: u32,
+    kind: EventKind,
+}
+
+/// Only note on, tempo and end of track events are kept
+pub struct MidiFile {
+    ticks_per_quarter: u32,
+    tracks: Vec<Vec<Event>>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    cursor: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn u8(&mut self) -> Result<u8, ::failure::Error> {
+        let byte = self.bytes
+            .get(self.cursor)
+            .cloned()
+            .ok_or(format_err!("unexpected end of file"))?;
+        self.cursor += 1;
+        Ok(byte)
+    }
+
+    fn u16(&mut self) -> Result<u16, ::failure::Error> {
+        Ok((self.u8()? as u16) << 8 | self.u8()? as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, ::failure::Error> {
+        Ok((self.u16()? as u32) << 16 | self.u16()? as u32)
+    }
+
+    /// Variable length quantity, at most 4 bytes
+    fn var_len(&mut self) -> Result<u32, ::failure::Error> {
+        let mut value = 0u32;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | (byte & 0x7f) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(format_err!("variable length quantity longer than 4 bytes"))
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), ::failure::Error> {
+        if self.cursor + len > self.bytes.len() {
+            return Err(format_err!("unexpected end of file"));
+        }
+        self.cursor += len;
+        Ok(())
+    }
+
+    fn tag(&mut self) -> Result<[u8; 4], ::failure::Error> {
+        Ok([self.u8()?, self.u8()?, self.u8()?, self.u8()?])
+    }
+}
+
+impl MidiFile {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ::failure::Error> {
+        let path = path.as_ref();
+        let mut bytes = vec![];
+        File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes))
+            .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+        MidiFile::parse(&bytes).map_err(|e| {
+            format_err!("\"{}\": invalid midi file: {}", path.to_string_lossy(), e)
+        })
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Self, ::failure::Error> {
+        let mut reader = Reader { bytes, cursor: 0 };
+
+        if &reader.tag()? != b"MThd" {
+            return Err(format_err!("missing MThd header"));
+        }
+        let header_len = reader.u32()? as usize;
+        if header_len < 6 {
+            return Err(format_err!("header too short"));
+        }
+        let _format = reader.u16()?;
+        let number_of_tracks = reader.u16()?;
+        let division = reader.u16()?;
+        reader.skip(header_len - 6)?;
+        if division & 0x8000 != 0 {
+            return Err(format_err!("SMPTE time division is not supported"));
+        }
+        if division == 0 {
+            return Err(format_err!("time division is 0"));
+        }
+
+        let mut tracks = vec![];
+        while tracks.len() < number_of_tracks as usize {
+            let tag = reader.tag()?;
+            let len = reader.u32()? as usize;
+            if &tag != b"MTrk" {
+                // Unknown chunks must be ignored
+                reader.skip(len)?;
+                continue;
+            }
+            let end = reader.cursor + len;
+            if end > bytes.len() {
+                return Err(format_err!("unexpected end of file"));
+            }
+            let mut track_reader = Reader {
+                bytes: &bytes[..end],
+                cursor: reader.cursor,
+            };
+            tracks.push(parse_track(&mut track_reader)?);
+            reader.cursor = end;
+        }
+
+        Ok(MidiFile {
+            ticks_per_quarter: division as u32,
+            tracks,
+        })
+    }
+
+    fn step_of_tick(&self, tick: u32) -> usize {
+        let ticks_per_step = self.ticks_per_quarter as f32 / STEPS_PER_QUARTER as f32;
+        (tick as f32 / ticks_per_step).round() as usize
+    }
+
+    /// Number of steps of the partitions, rounded up to a quarter note
+    pub fn len(&self) -> usize {
+        let end_tick = self.tracks
+            .iter()
+            .filter_map(|track| track.last())
+            .map(|event| event.tick)
+            .max()
+            .unwrap_or(0);
+        let quarters = (end_tick + self.ticks_per_quarter - 1) / self.ticks_per_quarter;
+        (quarters.max(1) * STEPS_PER_QUARTER) as usize
+    }
+
+    /// Beat pattern of note on events of the note in the track, one beat per step
+    pub fn partition(&self, track: usize, note: u8) -> Result<Vec<bool>, ::failure::Error> {
+        let events = self.tracks.get(track).ok_or(format_err!(
+            "track {} does not exist, file contains {} tracks",
+            track,
+            self.tracks.len()
+        ))?;
+
+        let len = self.len();
+        let mut partition = vec![false; len];
+        for event in events {
+            if event.kind == EventKind::NoteOn(note) {
+                partition[self.step_of_tick(event.tick) % len] = true;
+            }
+        }
+        Ok(partition)
+    }
+
+    /// Tempo with one beat per step, following the tempo changes of all tracks
+    pub fn tempo(&self) -> ::resource::Tempo {
+        let step_time = |microseconds_per_quarter: u32| {
+            microseconds_per_quarter as f32 / 1_000_000.0 / STEPS_PER_QUARTER as f32
+        };
+
+        let mut changes = self.tracks
+            .iter()
+            .flat_map(|track| track.iter())
+            .filter_map(|event| match event.kind {
+                EventKind::Tempo(tempo) => Some((self.step_of_tick(event.tick), step_time(tempo))),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Stable so that the last change of a step wins
+        changes.sort_by_key(|&(step, _)| step);
+
+        let mut tempo = ::resource::Tempo::new(step_time(DEFAULT_MICROSECONDS_PER_QUARTER));
+        tempo.set_changes(changes, self.len());
+        tempo
+    }
+}
+
+fn parse_track(reader: &mut Reader) -> Result<Vec<Event>, ::failure::Error> {
+    let mut events = vec![];
+    let mut tick = 0u32;
+    let mut running_status = None;
+
+    while reader.cursor < reader.bytes.len() {
+        tick = tick.saturating_add(reader.var_len()?);
+
+        let mut status = reader.u8()?;
+        let mut first_data = None;
+        if status < 0x80 {
+            first_data = Some(status);
+            status = running_status.ok_or(format_err!("data byte without running status"))?;
+        }
+
+        match status {
+            0xff => {
+                let meta_type = reader.u8()?;
+                let len = reader.var_len()? as usize;
+                match meta_type {
+                    0x51 if len == 3 => {
+                        let tempo = (reader.u8()? as u32) << 16
+                            | (reader.u16()? as u32);
+                        events.push(Event {
+                            tick,
+                            kind: EventKind::Tempo(tempo),
+                        });
+                    }
+                    0x2f => {
+                        reader.skip(len)?;
+                        events.push(Event {
+                            tick,
+                            kind: EventKind::EndOfTrack,
+                        });
+                        break;
+                    }
+                    _ => reader.skip(len)?,
+                }
+            }
+            0xf0 | 0xf7 => {
+                let len = reader.var_len()? as usize;
+                reader.skip(len)?;
+            }
+            0x80...0xef => {
+                running_status = Some(status);
+                let data_1 = match first_data {
+                    Some(data) => data,
+                    None => reader.u8()?,
+                };
+                match status & 0xf0 {
+                    0xc0 | 0xd0 => (),
+                    0x90 => {
+                        let velocity = reader.u8()?;
+                        // Note on with velocity 0 is a note off
+                        if velocity != 0 {
+                            events.push(Event {
+                                tick,
+                                kind: EventKind::NoteOn(data_1),
+                            });
+                        }
+                    }
+                    _ => {
+                        reader.u8()?;
+                    }
+                }
+            }
+            _ => return Err(format_err!("invalid status byte: {:#x}", status)),
+        }
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One track at 96 ticks per quarter:
+    /// tempo of 1s per quarter, note 60 on at tick 0, off at tick 96 and on at tick 192
+    /// with running status, tempo of 0.5s per quarter at tick 192, end at tick 384
+    const FIXTURE: &[u8] = &[
+        b'M', b'T', b'h', b'd', 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x01, 0x00, 0x60,
+        b'M', b'T', b'r', b'k', 0x00, 0x00, 0x00, 0x1d,
+        0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40,
+        0x00, 0x90, 0x3c, 0x64,
+        0x60, 0x3c, 0x00,
+        0x60, 0x3c, 0x64,
+        0x00, 0xff, 0x51, 0x03, 0x07, 0xa1, 0x20,
+        0x81, 0x40, 0xff, 0x2f, 0x00,
+    ];
+
+    fn var_len(bytes: &[u8]) -> Result<u32, ::failure::Error> {
+        Reader { bytes, cursor: 0 }.var_len()
+    }
+
+    #[test]
+    fn variable_length_quantities() {
+        assert_eq!(var_len(&[0x00]).unwrap(), 0);
+        assert_eq!(var_len(&[0x7f]).unwrap(), 0x7f);
+        assert_eq!(var_len(&[0x81, 0x00]).unwrap(), 0x80);
+        assert_eq!(var_len(&[0xff, 0x7f]).unwrap(), 0x3fff);
+        assert_eq!(var_len(&[0xff, 0xff, 0xff, 0x7f]).unwrap(), 0x0fff_ffff);
+        assert!(var_len(&[0xff, 0xff, 0xff, 0xff, 0x7f]).is_err());
+        assert!(var_len(&[0x81]).is_err());
+    }
+
+    #[test]
+    fn running_status() {
+        let midi = MidiFile::parse(FIXTURE).unwrap();
+        assert_eq!(
+            midi.tracks[0]
+                .iter()
+                .filter(|event| event.kind == EventKind::NoteOn(0x3c))
+                .map(|event| event.tick)
+                .collect::<Vec<_>>(),
+            vec![0, 192]
+        );
+
+        let mut partition = vec![false; 16];
+        partition[0] = true;
+        partition[8] = true;
+        assert_eq!(midi.len(), 16);
+        assert_eq!(midi.partition(0, 0x3c).unwrap(), partition);
+        assert!(midi.partition(1, 0x3c).is_err());
+    }
+
+    #[test]
+    fn data_byte_without_running_status() {
+        let mut bytes = FIXTURE[..14].to_vec();
+        bytes.extend_from_slice(&[b'M', b'T', b'r', b'k', 0x00, 0x00, 0x00, 0x03]);
+        bytes.extend_from_slice(&[0x00, 0x3c, 0x64]);
+        assert!(MidiFile::parse(&bytes).is_err());
+    }
+
+    #[test]
+    fn tempo_meta_events() {
+        let tempo = MidiFile::parse(FIXTURE).unwrap().tempo();
+        assert_eq!(tempo.time_at(0), 0.25);
+        assert_eq!(tempo.time_at(7), 0.25);
+        assert_eq!(tempo.time_at(8), 0.125);
+        assert_eq!(tempo.time_at(15), 0.125);
+        assert_eq!(tempo.time_at(16), 0.25);
+    }
+}
@@ -0,0 +1,188 @@
+use audio::{FadingControl, SoundBuffer};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MusicSettings {
+    /// Number of beats of the first tempo in a bar
+    pub bar: usize,
+    /// Duration of fade in and fade out of stems in seconds
+    pub crossfade: f32,
+    pub intensity: Intensity,
+    pub stems: Vec<StemSettings>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StemSettings {
+    pub file: PathBuf,
+    /// Number of bars of the loop
+    pub bars: usize,
+    /// The stem is played when the intensity is greater or equal
+    pub intensity: f32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub enum Intensity {
+    /// The processor takes the number of monsters
+    MonsterCount(::util::ClampFunction),
+    /// The processor takes the distance of the closest monster to the player
+    MonsterDistance(::util::ClampFunction),
+}
+
+impl Intensity {
+    pub fn compute(
+        &self,
+        player_position: Option<::na::Vector2<f32>>,
+        physic_world: &::resource::PhysicWorld,
+    ) -> f32 {
+        let monsters = physic_world
+            .collision_world()
+            .collision_objects()
+            .filter(|object| {
+                object
+                    .collision_groups()
+                    .is_member_of(::entity::Group::Monster as usize)
+            })
+            .map(|object| object.position().translation.vector);
+
+        match *self {
+            Intensity::MonsterCount(ref clamp) => clamp.compute(monsters.count() as f32),
+            Intensity::MonsterDistance(ref clamp) => {
+                let distance = player_position
+                    .and_then(|player_position| {
+                        monsters
+                            .map(|position| (position - player_position).norm())
+                            .min_by_key(|distance| (distance * ::CMP_PRECISION) as usize)
+                    })
+                    .unwrap_or(::std::f32::INFINITY);
+                clamp.compute(distance)
+            }
+        }
+    }
+}
+
+struct Stem {
+    buffer: SoundBuffer,
+    bars: usize,
+    intensity: f32,
+    playing: Option<Arc<FadingControl>>,
+}
+
+/// Stems are looped in sync with the first tempo
+///
+/// Stems are started and stopped on bar boundaries depending on the intensity
+pub struct Music {
+    bar: usize,
+    crossfade: f32,
+    intensity: Option<Intensity>,
+    stems: Vec<Stem>,
+    bars_started: usize,
+}
+
+impl Music {
+    /// Stems are resampled to sample_rate
+    pub fn load(settings: Option<MusicSettings>, sample_rate: u32) -> Result<Self, ::failure::Error> {
+        let settings = match settings {
+            Some(settings) => settings,
+            None => {
+                return Ok(Music {
+                    bar: 1,
+                    crossfade: 0.0,
+                    intensity: None,
+                    stems: vec![],
+                    bars_started: 0,
+                })
+            }
+        };
+
+        if settings.bar == 0 {
+            return Err(format_err!("music: bar must not be 0"));
+        }
+
+        let mut stems = vec![];
+        for stem in settings.stems {
+            if stem.bars == 0 {
+                return Err(format_err!(
+                    "music: stem \"{}\": bars must not be 0",
+                    stem.file.to_string_lossy()
+                ));
+            }
+            stems.push(Stem {
+                buffer: SoundBuffer::load(&stem.file, sample_rate)
+                    .map_err(|e| format_err!("music: {}", e))?,
+                bars: stem.bars,
+                intensity: stem.intensity,
+                playing: None,
+            });
+        }
+
+        Ok(Music {
+            bar: settings.bar,
+            crossfade: settings.crossfade,
+            intensity: Some(settings.intensity),
+            stems,
+            bars_started: 0,
+        })
+    }
+
+    pub fn intensity(&self) -> Option<&Intensity> {
+        self.intensity.as_ref()
+    }
+
+    pub fn update(
+        &mut self,
+        intensity: f32,
+        tempo: &::resource::Tempo,
+        volume: f32,
+        audio: &::resource::Audio,
+    ) {
+        let bars_started = (tempo.beat + self.bar - 1) / self.bar;
+        if bars_started != self.bars_started && bars_started > 0 {
+            self.bars_started = bars_started;
+
+            // The bar started during the last update
+            let bar_beat = (bars_started - 1) * self.bar;
+            let bar_time = tempo.next_beat_time - tempo.duration(bar_beat, tempo.beat);
+            let bar_samples =
+                tempo.duration(bar_beat, bar_beat + self.bar) * audio.sample_rate() as f32;
+
+            for stem in &mut self.stems {
+                let active = intensity >= stem.intensity;
+                match (active, stem.playing.is_some()) {
+                    (true, false) => {
+                        let len = (stem.bars as f32 * bar_samples) as usize;
+                        let bar_in_loop = (bars_started - 1) % stem.bars;
+                        let cursor = (bar_in_loop as f32 * bar_samples) as usize;
+                        stem.playing = Some(audio.play_loop(
+                            &stem.buffer,
+                            len,
+                            cursor,
+                            volume,
+                            self.crossfade,
+                            bar_time,
+                        ));
+                    }
+                    (false, true) => {
+                        stem.playing.take().unwrap().stop();
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        for control in self.stems.iter().filter_map(|stem| stem.playing.as_ref()) {
+            control.set_volume(volume);
+        }
+    }
+}
+
+impl Drop for Music {
+    fn drop(&mut self) {
+        for control in self.stems.iter().filter_map(|stem| stem.playing.as_ref()) {
+            control.stop();
+        }
+    }
+}
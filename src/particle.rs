@@ -0,0 +1,315 @@
+use rand::distributions::{Distribution, Range};
+use rand::{thread_rng, ThreadRng};
+use show_message::UnwrapOrShow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+/// Pick a value uniformly in the range, the minimum if the range is empty
+fn sample((min, max): (f32, f32), rng: &mut ThreadRng) -> f32 {
+    if max > min {
+        Range::new(min, max).sample(rng)
+    } else {
+        min
+    }
+}
+
+/// Interpolate linearly the keys regularly spread over the life, t in [0, 1]
+fn over_life<F: Fn(usize, usize, f32) -> T, T>(len: usize, t: f32, lerp: F) -> T {
+    if len <= 1 {
+        return lerp(0, 0, 0.0);
+    }
+    let position = t.min(1.0).max(0.0) * (len - 1) as f32;
+    let i = (position as usize).min(len - 2);
+    lerp(i, i + 1, position - i as f32)
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EmitterSettings {
+    /// Particles spawned at once when triggered
+    #[serde(default)]
+    pub burst: usize,
+    /// Particles spawned per second while the emitter lasts
+    #[serde(default)]
+    pub spawn_rate: f32,
+    /// Duration of the emitter, ambient emitters last while their entity lives
+    #[serde(default)]
+    pub duration: f32,
+    /// Minimal and maximal lifetime of particles
+    pub lifetime: (f32, f32),
+    /// Minimal and maximal initial speed of particles
+    pub speed: (f32, f32),
+    /// Angle of the cone around the emitter direction, 2π for all directions
+    pub cone: f32,
+    /// Part of the velocity lost per second
+    #[serde(default)]
+    pub drag: f32,
+    /// Colors regularly spread over the life of particles
+    pub colors: Vec<(f32, f32, f32, f32)>,
+    /// Sizes regularly spread over the life of particles
+    pub sizes: Vec<f32>,
+    /// Name of an image in the animation directory, plain quads if none
+    #[serde(default)]
+    pub sprite: Option<String>,
+}
+
+struct BankEmitter {
+    settings: EmitterSettings,
+    /// Index of the sprite in the animation images
+    sprite: Option<usize>,
+}
+
+/// Emitters declared in data/particles.ron, referenced by name
+pub struct ParticleBank {
+    emitters: Vec<BankEmitter>,
+    names: HashMap<String, usize>,
+}
+
+impl ParticleBank {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ::failure::Error> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+        let settings: HashMap<String, EmitterSettings> = ::ron::de::from_reader(file)
+            .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+
+        let mut emitters = vec![];
+        let mut names = HashMap::new();
+        for (name, settings) in settings {
+            if settings.colors.is_empty() || settings.sizes.is_empty() {
+                return Err(format_err!(
+                    "particle emitter \"{}\": colors and sizes must not be empty",
+                    name
+                ));
+            }
+            let sprite = match settings.sprite {
+                Some(ref sprite) => Some(
+                    ::animation::ANIMATIONS
+                        .images
+                        .iter()
+                        .position(|image| {
+                            image
+                                .file_stem()
+                                .map(|stem| stem.to_string_lossy() == sprite.as_str())
+                                .unwrap_or(false)
+                        })
+                        .ok_or_else(|| {
+                            format_err!(
+                                "particle emitter \"{}\": sprite \"{}\" is not an animation image",
+                                name,
+                                sprite
+                            )
+                        })?,
+                ),
+                None => None,
+            };
+            names.insert(name, emitters.len());
+            emitters.push(BankEmitter { settings, sprite });
+        }
+        Ok(ParticleBank { emitters, names })
+    }
+
+    /// Check that all emitters referenced by the insertables are declared
+    pub fn check(&self, insertables: &::resource::InsertablesMap) -> Result<(), ::failure::Error> {
+        for (name, insertable) in insertables {
+            for component in insertable.components() {
+                if let ::entity::MetaComponent::ParticleEffects(ref effects) = *component {
+                    for emitter in effects.emitters() {
+                        if !self.names.contains_key(emitter) {
+                            return Err(format_err!(
+                                "insertable \"{}\": particle emitter \"{}\" is not declared",
+                                name,
+                                emitter
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Particle {
+    emitter: usize,
+    position: ::na::Vector2<f32>,
+    velocity: ::na::Vector2<f32>,
+    age: f32,
+    lifetime: f32,
+}
+
+struct ActiveEmitter {
+    emitter: usize,
+    position: ::na::Isometry2<f32>,
+    remaining: f32,
+    /// Fractional particles not spawned yet
+    accumulator: f32,
+}
+
+/// Particle drawn as one instance of a quad
+#[derive(Debug, Clone)]
+pub struct ParticleInstance {
+    pub center: [f32; 2],
+    pub size: f32,
+    pub color: [f32; 4],
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+}
+
+/// Particles simulated on CPU, drawn by sprite batches
+pub struct Particles {
+    bank: ParticleBank,
+    emitters: Vec<ActiveEmitter>,
+    particles: Vec<Particle>,
+}
+
+impl Particles {
+    pub fn init(conf: &::resource::Conf) -> Self {
+        let bank = ParticleBank::load("data/particles.ron")
+            .unwrap_or_else_show(|e| format!("Failed to load particle bank: {}", e));
+        bank.check(&conf.insertables)
+            .unwrap_or_else_show(|e| format!("Invalid configuration: {}", e));
+        Particles {
+            bank,
+            emitters: vec![],
+            particles: vec![],
+        }
+    }
+
+    pub fn bank(&self) -> &ParticleBank {
+        &self.bank
+    }
+
+    pub fn clear(&mut self) {
+        self.emitters.clear();
+        self.particles.clear();
+    }
+
+    /// Spawn the burst and start the emitter, the cone is centered on the position angle
+    ///
+    /// Nothing is emitted if the emitter is unknown
+    pub fn emit(&mut self, emitter: &str, position: ::na::Isometry2<f32>) {
+        let emitter = match self.bank.names.get(emitter) {
+            Some(&emitter) => emitter,
+            None => return,
+        };
+        let settings = &self.bank.emitters[emitter].settings;
+        let mut rng = thread_rng();
+        for _ in 0..settings.burst {
+            self.particles
+                .push(Self::spawn(emitter, settings, &position, &mut rng));
+        }
+        if settings.duration > 0.0 && settings.spawn_rate > 0.0 {
+            self.emitters.push(ActiveEmitter {
+                emitter,
+                position,
+                remaining: settings.duration,
+                accumulator: 0.0,
+            });
+        }
+    }
+
+    /// Spawn particles of a continuous emitter for the elapsed time
+    pub fn emit_continuous(&mut self, emitter: &str, position: ::na::Isometry2<f32>, dt: f32) {
+        let emitter = match self.bank.names.get(emitter) {
+            Some(&emitter) => emitter,
+            None => return,
+        };
+        let settings = &self.bank.emitters[emitter].settings;
+        let mut rng = thread_rng();
+        let count = settings.spawn_rate * dt;
+        let count = count as usize
+            + if Range::new(0.0, 1.0).sample(&mut rng) < count.fract() {
+                1
+            } else {
+                0
+            };
+        for _ in 0..count {
+            self.particles
+                .push(Self::spawn(emitter, settings, &position, &mut rng));
+        }
+    }
+
+    fn spawn(
+        emitter: usize,
+        settings: &EmitterSettings,
+        position: &::na::Isometry2<f32>,
+        rng: &mut ThreadRng,
+    ) -> Particle {
+        let angle = position.rotation.angle()
+            + sample((-settings.cone / 2.0, settings.cone / 2.0), rng);
+        Particle {
+            emitter,
+            position: position.translation.vector,
+            velocity: ::na::Vector2::new(angle.cos(), angle.sin()) * sample(settings.speed, rng),
+            age: 0.0,
+            lifetime: sample(settings.lifetime, rng),
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let mut rng = thread_rng();
+        for active in &mut self.emitters {
+            let settings = &self.bank.emitters[active.emitter].settings;
+            let dt = dt.min(active.remaining);
+            active.remaining -= dt;
+            active.accumulator += settings.spawn_rate * dt;
+            while active.accumulator >= 1.0 {
+                active.accumulator -= 1.0;
+                self.particles.push(Self::spawn(
+                    active.emitter,
+                    settings,
+                    &active.position,
+                    &mut rng,
+                ));
+            }
+        }
+        self.emitters.retain(|active| active.remaining > 0.0);
+
+        for particle in &mut self.particles {
+            let drag = self.bank.emitters[particle.emitter].settings.drag;
+            particle.age += dt;
+            particle.velocity *= (1.0 - drag * dt).max(0.0);
+            particle.position += particle.velocity * dt;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+
+    /// Instances of the particles grouped by atlas page, none for plain quads
+    pub fn instances(&self) -> HashMap<Option<usize>, Vec<ParticleInstance>> {
+        let mut batches = HashMap::new();
+        for particle in &self.particles {
+            let emitter = &self.bank.emitters[particle.emitter];
+            let settings = &emitter.settings;
+            let t = particle.age / particle.lifetime;
+            let color = over_life(settings.colors.len(), t, |i, j, f| {
+                let (a, b) = (settings.colors[i], settings.colors[j]);
+                [
+                    a.0 + (b.0 - a.0) * f,
+                    a.1 + (b.1 - a.1) * f,
+                    a.2 + (b.2 - a.2) * f,
+                    a.3 + (b.3 - a.3) * f,
+                ]
+            });
+            let size = over_life(settings.sizes.len(), t, |i, j, f| {
+                settings.sizes[i] + (settings.sizes[j] - settings.sizes[i]) * f
+            });
+            let rect = emitter
+                .sprite
+                .map(|sprite| ::animation::ANIMATIONS.atlas.rects[sprite]);
+            batches
+                .entry(rect.map(|rect| rect.page))
+                .or_insert_with(Vec::new)
+                .push(ParticleInstance {
+                    center: [particle.position[0], -particle.position[1]],
+                    size,
+                    color,
+                    uv_min: rect.map(|rect| rect.uv_min).unwrap_or([0.0, 0.0]),
+                    uv_max: rect.map(|rect| rect.uv_max).unwrap_or([1.0, 1.0]),
+                });
+        }
+        batches
+    }
+}
@@ -0,0 +1,509 @@
+//! Drawing of the world independent of the graphics backend
+
+use itertools::Itertools;
+use ncollide2d::shape::{self, Segment, ShapeHandle};
+use png::HasParameters;
+use specs::{Join, RunNow, World};
+use std::cmp::Ordering;
+use std::f32::consts::PI;
+use std::fs::File;
+use std::path::Path;
+
+const DEBUG_SEGMENT_WIDTH: f32 = 0.2;
+const DEBUG_RAY_LENGTH: f32 = 10.0;
+/// Color of the background of software captures
+const CLEAR_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+/// Position in world units with y negated
+#[derive(Debug, Clone)]
+pub struct DebugVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+
+#[derive(Debug, Clone)]
+pub struct SpriteInstance {
+    /// Center in world units with y negated
+    pub position: [f32; 2],
+    /// Angle with y negated
+    pub angle: f32,
+    /// Size in world units
+    pub size: [f32; 2],
+    pub layer: f32,
+    pub uv_min: [f32; 2],
+    pub uv_max: [f32; 2],
+    pub color: [f32; 4],
+    /// From 0 for no flash to 1 for plain white
+    pub flash: f32,
+}
+
+/// Backend the world is drawn with, in the order of the calls
+pub trait RenderBackend {
+    /// Background then walls of the map
+    fn draw_map(&mut self, map_geometry: &::resource::MapGeometry);
+    /// Animation images of one page of the atlas
+    fn draw_sprites(&mut self, page: usize, instances: Vec<SpriteInstance>);
+    /// Debug shapes of the physic world
+    fn draw_triangles(&mut self, vertices: Vec<DebugVertex>);
+}
+
+/// Draw the map, the animation images sorted by layer and the debug shapes
+///
+/// Animation images and debug shapes are not drained
+pub fn draw_world<B: RenderBackend>(world: &World, backend: &mut B) {
+    backend.draw_map(&world.read_resource::<::resource::MapGeometry>());
+
+    let images = world.read_resource::<::resource::AnimationImages>();
+    let mut images = images.iter().collect::<Vec<_>>();
+    images.sort_by(|a, b| a.layer.partial_cmp(&b.layer).unwrap_or(Ordering::Equal));
+    // One batch per run of the same page to keep the layer order
+    for (page, batch) in &images.into_iter().group_by(|image| image.rect.page) {
+        let instances = batch
+            .map(|image| SpriteInstance {
+                position: [
+                    image.position.translation.vector[0],
+                    -image.position.translation.vector[1],
+                ],
+                angle: -image.position.rotation.angle(),
+                size: [
+                    image.rect.width as f32 * image.scale,
+                    image.rect.height as f32 * image.scale,
+                ],
+                layer: image.layer,
+                uv_min: image.rect.uv_min,
+                uv_max: image.rect.uv_max,
+                color: image.tint,
+                flash: image.flash,
+            })
+            .collect::<Vec<_>>();
+        backend.draw_sprites(page, instances);
+    }
+
+    let bodies_map = world.read_resource::<::resource::BodiesMap>();
+    let debug_colors = world.read_storage::<::component::DebugColor>();
+    let aims = world.read_storage::<::component::Aim>();
+    let debug_circles = world.read_storage::<::component::DebugCircles>();
+    let debug_rays = world.read_storage::<::component::DebugRays>();
+    let visibles = world.read_storage::<::component::Visible>();
+    let bodies = world.read_storage::<::component::RigidBody>();
+    let physic_world = world.read_resource::<::resource::PhysicWorld>();
+    let debug_shapes = world.read_resource::<::resource::DebugShapes>();
+
+    let mut vertices = vec![];
+    for collider in physic_world.colliders() {
+        let entity = bodies_map.get(&collider.data().body());
+        if entity.map(|e| visibles.get(*e).is_none()).unwrap_or(false) {
+            continue;
+        }
+        let color = entity
+            .and_then(|e| debug_colors.get(*e))
+            .map(|c| c.0)
+            .unwrap_or(0);
+        shape_vertices(
+            collider.position(),
+            collider.shape(),
+            COLORS[color],
+            &mut vertices,
+        );
+    }
+    for (radiuss, color, body, _) in (&debug_circles, &debug_colors, &bodies, &visibles).join() {
+        let body = body.get(&physic_world);
+        for &radius in radiuss.iter() {
+            circle_vertices(&body.position(), radius, COLORS[color.0], &mut vertices);
+        }
+    }
+    for &(ref position, ref shape) in debug_shapes.iter() {
+        shape_vertices(position, shape, COLORS[0], &mut vertices);
+    }
+
+    let segment = ShapeHandle::new(Segment::new(
+        ::na::Point2::new(0.0, 0.0),
+        ::na::Point2::new(DEBUG_RAY_LENGTH, 0.0),
+    ));
+    for (rays, body, color, _) in (&debug_rays, &bodies, &debug_colors, &visibles).join() {
+        let position = body.get(&physic_world).position();
+        for &ray in rays.iter() {
+            let position = position * ::na::UnitComplex::new(ray);
+            shape_vertices(&position, &segment, COLORS[color.0], &mut vertices);
+        }
+    }
+    for (aim, body, color, _) in (&aims, &bodies, &debug_colors, &visibles).join() {
+        let position = body.get(&physic_world).position();
+        let position = position * ::na::UnitComplex::new(aim.0);
+        shape_vertices(&position, &segment, COLORS[color.0], &mut vertices);
+    }
+
+    backend.draw_triangles(vertices);
+}
+
+/// Rasterize on CPU, for captures on machines without GPU
+pub struct SoftwareBackend {
+    view: ::na::Matrix4<f32>,
+    width: u32,
+    height: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+impl SoftwareBackend {
+    pub fn new(view: [[f32; 4]; 4], dimensions: [u32; 2]) -> Self {
+        SoftwareBackend {
+            view: ::na::Matrix4::from(view),
+            width: dimensions[0],
+            height: dimensions[1],
+            pixels: vec![CLEAR_COLOR; (dimensions[0] * dimensions[1]) as usize],
+        }
+    }
+
+    /// Draw the world as seen by the camera
+    pub fn capture(world: &World, dimensions: [u32; 2]) -> Self {
+        let view = world
+            .read_resource::<::resource::Camera>()
+            .matrix(dimensions);
+        let mut backend = SoftwareBackend::new(view, dimensions);
+        draw_world(world, &mut backend);
+        backend
+    }
+
+    /// Load the map in a world without window and capture it around the player
+    /// with the visibility of the first update
+    pub fn capture_map(name: &str, dimensions: [u32; 2]) -> Result<Self, ::failure::Error> {
+        let conf = ::resource::Conf::load();
+        let save = ::resource::Save::load();
+        let audio =
+            ::resource::Audio::init_offline(&conf, &save, ::audio::DEFAULT_SAMPLE_RATE, false);
+        let mut world = World::new();
+        ::init_world(&mut world, conf, save, audio);
+        world.maintain();
+        ::map::load_map(name.into(), &mut world)?;
+        world.maintain();
+
+        // Update the collision world for the visibility ray casts
+        world.write_resource::<::resource::PhysicWorld>().step();
+        RunNow::run_now(&mut ::system::VisibilitySystem, &world.res);
+        world.maintain();
+
+        let player_position = {
+            let players = world.read_storage::<::component::Player>();
+            let bodies = world.read_storage::<::component::RigidBody>();
+            let physic_world = world.read_resource::<::resource::PhysicWorld>();
+            (&players, &bodies)
+                .join()
+                .next()
+                .map(|(_, body)| *body.get(&physic_world).position())
+        };
+        if let Some(position) = player_position {
+            world.write_resource::<::resource::Camera>().position =
+                ::na::Isometry2::new(position.translation.vector, 0.0);
+        }
+
+        Ok(SoftwareBackend::capture(&world, dimensions))
+    }
+
+    /// RGBA pixels of 8 bits channels
+    fn data(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| pixel.iter())
+            .map(|&c| (c.min(1.0).max(0.0) * 255.0).round() as u8)
+            .collect()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ::failure::Error> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| format_err!("\"{}\": {}", path.to_string_lossy(), e))?;
+        let mut encoder = ::png::Encoder::new(file, self.width, self.height);
+        encoder
+            .set(::png::ColorType::RGBA)
+            .set(::png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.data())?;
+        Ok(())
+    }
+
+    /// Position in pixels from the top left corner
+    fn to_pixel(&self, position: [f32; 2]) -> [f32; 2] {
+        let p = self.view * ::na::Vector4::new(position[0], position[1], 0.0, 1.0);
+        [
+            (p[0] / p[3] + 1.0) / 2.0 * self.width as f32,
+            (p[1] / p[3] + 1.0) / 2.0 * self.height as f32,
+        ]
+    }
+
+    /// Blend the color returned by the shader for the barycentric coordinates of each pixel
+    fn rasterize<F>(&mut self, triangle: [[f32; 2]; 3], shader: F)
+    where
+        F: Fn([f32; 3]) -> Option<[f32; 4]>,
+    {
+        let [a, b, c] = [
+            self.to_pixel(triangle[0]),
+            self.to_pixel(triangle[1]),
+            self.to_pixel(triangle[2]),
+        ];
+        let edge = |p: [f32; 2], q: [f32; 2], r: [f32; 2]| {
+            (q[0] - p[0]) * (r[1] - p[1]) - (q[1] - p[1]) * (r[0] - p[0])
+        };
+        let area = edge(a, b, c);
+        if area.abs() <= ::std::f32::EPSILON {
+            return;
+        }
+
+        let min_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as u32;
+        let min_y = a[1].min(b[1]).min(c[1]).floor().max(0.0) as u32;
+        let max_x = (a[0].max(b[0]).max(c[0]).ceil().max(0.0) as u32).min(self.width);
+        let max_y = (a[1].max(b[1]).max(c[1]).ceil().max(0.0) as u32).min(self.height);
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let barycentric = [
+                    edge(b, c, p) / area,
+                    edge(c, a, p) / area,
+                    edge(a, b, p) / area,
+                ];
+                if barycentric.iter().any(|&w| w < 0.0) {
+                    continue;
+                }
+                if let Some(color) = shader(barycentric) {
+                    let pixel = &mut self.pixels[(y * self.width + x) as usize];
+                    for i in 0..3 {
+                        pixel[i] = color[i] * color[3] + pixel[i] * (1.0 - color[3]);
+                    }
+                    pixel[3] = color[3] + pixel[3] * (1.0 - color[3]);
+                }
+            }
+        }
+    }
+}
+
+impl RenderBackend for SoftwareBackend {
+    fn draw_map(&mut self, map_geometry: &::resource::MapGeometry) {
+        for vertices in &[&map_geometry.background, &map_geometry.walls] {
+            for triangle in vertices.chunks(3).filter(|triangle| triangle.len() == 3) {
+                let color = triangle[0].color;
+                let position = |i: usize| [triangle[i].position[0], -triangle[i].position[1]];
+                self.rasterize([position(0), position(1), position(2)], |_| Some(color));
+            }
+        }
+    }
+
+    fn draw_sprites(&mut self, page: usize, instances: Vec<SpriteInstance>) {
+        let page = &::animation::ANIMATIONS.atlas.pages[page];
+        let corners = [[-0.5f32, -0.5], [-0.5, 0.5], [0.5, -0.5], [0.5, 0.5]];
+        for instance in instances {
+            let (sin, cos) = instance.angle.sin_cos();
+            let position = |corner: [f32; 2]| {
+                let p = [corner[0] * instance.size[0], corner[1] * instance.size[1]];
+                [
+                    instance.position[0] + cos * p[0] - sin * p[1],
+                    instance.position[1] + sin * p[0] + cos * p[1],
+                ]
+            };
+            let uv = |corner: [f32; 2]| {
+                let (min, max) = (instance.uv_min, instance.uv_max);
+                [
+                    min[0] + (max[0] - min[0]) * (corner[0] + 0.5),
+                    min[1] + (max[1] - min[1]) * (corner[1] + 0.5),
+                ]
+            };
+            for triangle in &[[0, 1, 2], [3, 1, 2]] {
+                let uvs = [
+                    uv(corners[triangle[0]]),
+                    uv(corners[triangle[1]]),
+                    uv(corners[triangle[2]]),
+                ];
+                let color = instance.color;
+                let flash = instance.flash;
+                self.rasterize(
+                    [
+                        position(corners[triangle[0]]),
+                        position(corners[triangle[1]]),
+                        position(corners[triangle[2]]),
+                    ],
+                    |w| {
+                        let u = uvs[0][0] * w[0] + uvs[1][0] * w[1] + uvs[2][0] * w[2];
+                        let v = uvs[0][1] * w[0] + uvs[1][1] * w[1] + uvs[2][1] * w[2];
+                        let x = ((u * page.width as f32) as u32).min(page.width - 1);
+                        let y = ((v * page.height as f32) as u32).min(page.height - 1);
+                        let texel = &page.pixels[((y * page.width + x) * 4) as usize..][..4];
+                        let alpha = texel[3] as f32 / 255.0 * color[3];
+                        if alpha <= 0.0 {
+                            return None;
+                        }
+                        let channel = |i: usize| {
+                            let c = texel[i] as f32 / 255.0 * color[i];
+                            c + (1.0 - c) * flash
+                        };
+                        Some([channel(0), channel(1), channel(2), alpha])
+                    },
+                );
+            }
+        }
+    }
+
+    fn draw_triangles(&mut self, vertices: Vec<DebugVertex>) {
+        for triangle in vertices.chunks(3).filter(|triangle| triangle.len() == 3) {
+            let color = triangle[0].color;
+            self.rasterize(
+                [
+                    triangle[0].position,
+                    triangle[1].position,
+                    triangle[2].position,
+                ],
+                |_| Some(color),
+            );
+        }
+    }
+}
+
+const DIV: usize = 16;
+
+lazy_static! {
+    static ref DISK: Vec<::na::Point2<f32>> = (0..DIV + 1)
+        .flat_map(|i| {
+            let a1 = i as f32 * 2.0 * PI / DIV as f32;
+            let a2 = (i + 1) as f32 * 2.0 * PI / DIV as f32;
+
+            vec![
+                ::na::Point2::new(a1.cos(), a1.sin()),
+                ::na::Point2::new(a2.cos(), a2.sin()),
+                ::na::Point2::new(0.0, 0.0),
+            ]
+        })
+        .collect::<Vec<_>>();
+}
+
+lazy_static! {
+    static ref CIRCLE: Vec<::na::Point2<f32>> = (0..DIV + 1)
+        .flat_map(|i| {
+            let outer_radius = 1.05;
+            let inner_radius = 0.95;
+
+            let a1 = i as f32 * 2.0 * PI / DIV as f32;
+            let a2 = (i + 1) as f32 * 2.0 * PI / DIV as f32;
+
+            let p1 = ::na::Point2::new(a1.cos(), a1.sin());
+            let p2 = ::na::Point2::new(a2.cos(), a2.sin());
+
+            let inner_p1 = p1 * inner_radius;
+            let outer_p1 = p1 * outer_radius;
+
+            let inner_p2 = p2 * inner_radius;
+            let outer_p2 = p2 * outer_radius;
+
+            vec![outer_p1, inner_p1, outer_p2, outer_p2, inner_p2, inner_p1]
+        })
+        .collect::<Vec<_>>();
+}
+
+lazy_static! {
+    pub static ref COLORS: Vec<[f32; 4]> = vec![
+        [1.0, 0.0, 0.0, 1.0],
+        [1.0, 1.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0, 1.0],
+        [0.0, 1.0, 1.0, 1.0],
+        [0.0, 0.0, 1.0, 1.0],
+        [1.0, 0.0, 1.0, 1.0],
+        [1.0, 1.0, 1.0, 1.0],
+    ];
+}
+
+fn circle_vertices(
+    position: &::na::Isometry2<f32>,
+    radius: f32,
+    color: [f32; 4],
+    vertices: &mut Vec<DebugVertex>,
+) {
+    vertices.extend(CIRCLE.iter().map(|p| *p * radius).map(|p| {
+        let p = position * p;
+        DebugVertex {
+            position: [p[0], -p[1]],
+            color,
+        }
+    }));
+}
+
+fn shape_vertices(
+    position: &::na::Isometry2<f32>,
+    shape: &ShapeHandle<f32>,
+    color: [f32; 4],
+    vertices: &mut Vec<DebugVertex>,
+) {
+    if let Some(ball) = shape.as_shape::<shape::Ball<f32>>() {
+        vertices.extend(DISK.iter().map(|p| *p * ball.radius()).map(|p| {
+            let p = position * p;
+            DebugVertex {
+                position: [p[0], -p[1]],
+                color,
+            }
+        }));
+    }
+    if let Some(convex_polygon) = shape.as_shape::<shape::ConvexPolygon<f32>>() {
+        let mut points_iter = convex_polygon.points().iter();
+        let pivot = points_iter.next().unwrap();
+        vertices.extend(
+            points_iter
+                .tuple_windows()
+                .flat_map(|(p1, p2)| vec![pivot, p1, p2])
+                .map(|p| {
+                    let p = position * *p;
+                    DebugVertex {
+                        position: [p[0], -p[1]],
+                        color,
+                    }
+                })
+                .collect::<Vec<_>>(),
+        );
+    }
+    if let Some(segment) = shape.as_shape::<shape::Segment<f32>>() {
+        let direction = segment.scaled_direction().normalize();
+        let normal = ::na::Vector2::new(-direction[1], direction[0]);
+
+        vertices.extend(
+            [
+                segment.a() + normal * DEBUG_SEGMENT_WIDTH / 2.0,
+                segment.a() - normal * DEBUG_SEGMENT_WIDTH / 2.0,
+                segment.b() - normal * DEBUG_SEGMENT_WIDTH / 2.0,
+                segment.b() + normal * DEBUG_SEGMENT_WIDTH / 2.0,
+                segment.b() - normal * DEBUG_SEGMENT_WIDTH / 2.0,
+                segment.a() + normal * DEBUG_SEGMENT_WIDTH / 2.0,
+            ].iter()
+                .map(|p| {
+                    let p = position * *p;
+                    DebugVertex {
+                        position: [p[0], -p[1]],
+                        color,
+                    }
+                })
+                .collect::<Vec<_>>(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Capture of the first map, written with `fefe capture-map one 320 240 FILE`
+    const GOLDEN: &str = "data/tests/capture_one.png";
+    const DIMENSIONS: [u32; 2] = [320, 240];
+    /// Maximal difference of a channel with the golden image, for float rounding
+    const TOLERANCE: u8 = 2;
+
+    #[test]
+    fn capture_matches_golden() {
+        let file = File::open(GOLDEN).unwrap_or_else(|e| panic!("\"{}\": {}", GOLDEN, e));
+        let (info, mut reader) = ::png::Decoder::new(file).read_info().unwrap();
+        assert_eq!([info.width, info.height], DIMENSIONS);
+        assert_eq!(info.color_type, ::png::ColorType::RGBA);
+        let mut golden = vec![0; info.buffer_size()];
+        reader.next_frame(&mut golden).unwrap();
+
+        let data = SoftwareBackend::capture_map("one", DIMENSIONS).unwrap().data();
+        assert_eq!(data.len(), golden.len());
+        let different = data.iter()
+            .zip(golden.iter())
+            .filter(|&(&a, &b)| (a as i16 - b as i16).abs() > TOLERANCE as i16)
+            .count();
+        assert_eq!(different, 0, "capture differs from \"{}\"", GOLDEN);
+    }
+}
@@ -1,17 +1,29 @@
 use entity::{FillableObject, InsertableObject, SegmentableObject};
 use fnv::FnvHashMap;
-use ncollide2d::shape::ShapeHandle;
+use ncollide2d::query::Ray;
+use ncollide2d::shape::{Segment, ShapeHandle};
+use ncollide2d::world::CollisionGroups;
 use nphysics2d::object::BodyHandle;
 use specs::Entity;
 use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::f32::EPSILON;
 use std::fs::File;
 use std::path::PathBuf;
 use app_dirs2::{AppInfo, AppDataType, app_root};
+use show_message::UnwrapOrShow;
 
 pub use imgui::ImGui;
 pub use specs::world::EntitiesRes;
 pub use specs::world::LazyUpdate;
 pub use audio::Audio;
+pub use music::Music;
+pub use map_geometry::MapGeometry;
+pub use particle::Particles;
+pub use text::Texts;
+pub use hud::HudShapes;
+pub use config_menu::ConfigMenu;
+pub use inspector::Inspector;
 
 const APP_INFO: AppInfo = AppInfo { name: "fefe", author: "thiolliere" };
 const FILENAME: &str = "save.ron";
@@ -27,7 +39,20 @@ lazy_static! {
 #[derive(Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Save {
+    /// Master volume
     pub audio_volume: f32,
+    #[serde(default = "default_bus_volume")]
+    pub music_volume: f32,
+    #[serde(default = "default_bus_volume")]
+    pub sfx_volume: f32,
+    #[serde(default = "default_bus_volume")]
+    pub activator_volume: f32,
+    #[serde(default = "default_bus_volume")]
+    pub ui_volume: f32,
+}
+
+fn default_bus_volume() -> f32 {
+    1.0
 }
 
 impl Save {
@@ -37,8 +62,19 @@ impl Save {
             .unwrap_or(Save {
                 // TODO:
                 audio_volume: 0.2,
+                music_volume: default_bus_volume(),
+                sfx_volume: default_bus_volume(),
+                activator_volume: default_bus_volume(),
+                ui_volume: default_bus_volume(),
             })
     }
+
+    pub fn save(&self) -> Result<(), ::failure::Error> {
+        let string = ::ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|e| format_err!("{}", e))?;
+        ::std::fs::write(SAVE_PATH.as_path(), string)
+            .map_err(|e| format_err!("\"{}\": {}", SAVE_PATH.to_string_lossy(), e))
+    }
 }
 
 pub struct WindowSize(pub (u32, u32));
@@ -51,6 +87,9 @@ pub struct Tempo {
     pub time: f32,
     pub next_beat_time: f32,
     pub beat: usize,
+    /// Changes of time: (beat, time) sorted by beat, looping every `len` beats
+    changes: Vec<(usize, f32)>,
+    len: usize,
 }
 
 impl Tempo {
@@ -59,13 +98,121 @@ impl Tempo {
             time,
             next_beat_time: 0.0,
             beat: 0,
+            changes: vec![],
+            len: 1,
         }
     }
+
+    pub fn set_changes(&mut self, changes: Vec<(usize, f32)>, len: usize) {
+        self.changes = changes;
+        self.len = len.max(1);
+    }
+
+    /// Duration of the beats from start included to end excluded
+    pub fn duration(&self, start: usize, end: usize) -> f32 {
+        (start..end).map(|beat| self.time_at(beat)).sum()
+    }
+
+    /// Duration of the beat
+    pub fn time_at(&self, beat: usize) -> f32 {
+        let beat = beat % self.len;
+        self.changes
+            .iter()
+            .take_while(|&&(change_beat, _)| change_beat <= beat)
+            .last()
+            .map(|&(_, time)| time)
+            .unwrap_or(self.time)
+    }
 }
 
+/// Number of segments of debug circles
+const DEBUG_CIRCLE_DIVISIONS: usize = 32;
+/// Half of the size of the cross of debug markers
+const DEBUG_MARKER_SIZE: f32 = 0.3;
+
 #[derive(Deref, DerefMut)]
 pub struct DebugShapes(pub Vec<(::na::Isometry2<f32>, ShapeHandle<f32>)>);
 
+impl DebugShapes {
+    pub fn push_segment(&mut self, a: ::na::Vector2<f32>, b: ::na::Vector2<f32>) {
+        if (b - a).norm() < EPSILON {
+            return;
+        }
+        let segment = Segment::new(
+            ::na::Point2::from_coordinates(a),
+            ::na::Point2::from_coordinates(b),
+        );
+        self.push((::na::one(), ShapeHandle::new(segment)));
+    }
+
+    /// Segments between consecutive points, and between the last and the first if closed
+    pub fn push_polyline(&mut self, points: &[::na::Vector2<f32>], closed: bool) {
+        for window in points.windows(2) {
+            self.push_segment(window[0], window[1]);
+        }
+        if closed && points.len() > 2 {
+            self.push_segment(points[points.len() - 1], points[0]);
+        }
+    }
+
+    pub fn push_circle(&mut self, center: ::na::Vector2<f32>, radius: f32) {
+        let points = (0..DEBUG_CIRCLE_DIVISIONS)
+            .map(|i| 2.0 * PI * i as f32 / DEBUG_CIRCLE_DIVISIONS as f32)
+            .map(|angle| center + ::na::Vector2::new(angle.cos(), angle.sin()) * radius)
+            .collect::<Vec<_>>();
+        self.push_polyline(&points, true);
+    }
+
+    /// Cross at the position
+    pub fn push_marker(&mut self, position: ::na::Vector2<f32>) {
+        let (x, y) = (
+            ::na::Vector2::new(DEBUG_MARKER_SIZE, 0.0),
+            ::na::Vector2::new(0.0, DEBUG_MARKER_SIZE),
+        );
+        self.push_segment(position - x, position + x);
+        self.push_segment(position - y, position + y);
+    }
+
+    /// Ray cast toward the target as done by the AI, stopped by walls and players
+    pub fn push_line_of_sight(
+        &mut self,
+        origin: ::na::Vector2<f32>,
+        target: ::na::Vector2<f32>,
+        physic_world: &PhysicWorld,
+    ) {
+        let ray = Ray::new(::na::Point2::from_coordinates(origin), target - origin);
+        let mut collision_groups = CollisionGroups::new();
+        collision_groups.set_whitelist(&[
+            ::entity::Group::Wall as usize,
+            ::entity::Group::Player as usize,
+        ]);
+        let toi = physic_world
+            .collision_world()
+            .interferences_with_ray(&ray, &collision_groups)
+            .map(|(_, intersection)| intersection.toi)
+            .min_by_key(|toi| (toi * ::CMP_PRECISION) as isize)
+            .unwrap_or(1.0);
+        self.push_segment(origin, origin + (target - origin) * toi);
+    }
+}
+
+/// AI systems publishing their debug shapes, toggled in the inspector
+#[derive(Default)]
+pub struct AiDebug {
+    /// Line of sight rays and last closest player in sight
+    pub velocity_to_player_memory: bool,
+    /// Line of sight rays
+    pub velocity_to_player_random: bool,
+    /// Path and next point
+    pub position_in_path: bool,
+    /// Radii of the neighbour clamp
+    pub boid: bool,
+    /// Next firing angle of each part
+    pub turret_spawner: bool,
+    /// Cones of the aim damping clamp around players aim
+    pub velocity_dampings: bool,
+}
+
 #[derive(Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Conf {
@@ -73,10 +220,34 @@ pub struct Conf {
     pub physic_max_timestep: f32,
     pub physic_min_timestep: f32,
     pub zoom: f32,
+    /// Maximal distance seen by the player
+    pub visibility_range: f32,
+    pub camera: ::graphics::CameraSettings,
+    /// No minimap if none
+    #[serde(default)]
+    pub minimap: Option<::hud::MinimapSettings>,
 
     pub audio_clamp_start: f32,
     pub audio_clamp_end: f32,
     pub audio_unit: f32,
+    /// Delay in seconds of sounds played on beats
+    pub audio_latency: f32,
+    /// Ducking of the music bus by the sfx bus
+    pub audio_ducking: ::dynamic_mixer::DuckingSettings,
+    pub audio_limiter: ::dynamic_mixer::LimiterSettings,
+    /// Cutoff frequency in Hz of the low-pass of sounds depending on their distance
+    pub audio_distance_cutoff: ::util::ClampFunction,
+    /// Raycast through walls between sounds and the listener if some
+    pub audio_occlusion: Option<::audio::OcclusionSettings>,
+    /// Speed of sound in world unit per second for Doppler effect
+    pub audio_sound_speed: f32,
+
+    /// Time of the tempos available to activators, MIDI tempos are added after
+    pub tempos: Vec<f32>,
+
+    /// Colors the tint of entities is picked in
+    #[serde(default)]
+    pub palettes: HashMap<String, Vec<(f32, f32, f32)>>,
 
     pub insertables: HashMap<String, InsertableObject>,
     pub fillables: HashMap<String, FillableObject>,
@@ -85,7 +256,62 @@ pub struct Conf {
 
 impl Conf {
     pub(crate) fn load() -> Self {
-        ::ron::de::from_reader(File::open("data/configuration.ron").unwrap()).unwrap()
+        let conf: Conf =
+            ::ron::de::from_reader(File::open("data/configuration.ron").unwrap()).unwrap();
+        conf.check()
+            .unwrap_or_else_show(|e| format!("Invalid configuration: {}", e));
+        conf
+    }
+
+    fn check(&self) -> Result<(), ::failure::Error> {
+        if self.tempos.is_empty() {
+            return Err(format_err!("tempos must not be empty"));
+        }
+        if let Some(&time) = self.tempos.iter().find(|&&time| time <= 0.0) {
+            return Err(format_err!("tempo time must be positive, found {}", time));
+        }
+        self.check_palettes(&self.insertables)?;
+        self.check_tempos(&self.insertables)
+    }
+
+    /// Check that the tempos of the activators with beats partition are declared
+    pub fn check_tempos(&self, insertables: &InsertablesMap) -> Result<(), ::failure::Error> {
+        for (name, insertable) in insertables {
+            for component in insertable.components() {
+                if let ::entity::MetaComponent::Activators(ref activators) = *component {
+                    for activator in activators.iter() {
+                        if let ::component::Partition::Beats(_) = activator.partition {
+                            if activator.tempo >= self.tempos.len() {
+                                return Err(format_err!(
+                                    "insertable \"{}\": tempo {} is not declared",
+                                    name,
+                                    activator.tempo
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that all palettes of the insertables appearances are declared
+    pub fn check_palettes(&self, insertables: &InsertablesMap) -> Result<(), ::failure::Error> {
+        for (name, insertable) in insertables {
+            if let InsertableObject::Meta(ref meta) = *insertable {
+                if let Some(ref palette) = meta.appearance.palette {
+                    if !self.palettes.contains_key(palette) {
+                        return Err(format_err!(
+                            "insertable \"{}\": palette \"{}\" is not declared",
+                            name,
+                            palette
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
     }
 }
 
@@ -94,8 +320,17 @@ pub type PhysicWorld = ::nphysics2d::world::World<f32>;
 #[derive(Deref, DerefMut)]
 pub struct UpdateTime(pub f32);
 pub use animation::AnimationImages;
+pub use animation::DeathAnimations;
+pub use animation::AnimationEvents;
+
+/// Visibility polygon of the player, None if there is no player
+pub struct Visibility(pub Option<::visibility::VisibilityPolygon>);
 pub use graphics::Camera;
 
+/// Camera zones of the map
+#[derive(Deref, DerefMut)]
+pub struct CameraZones(pub Vec<::graphics::CameraZone>);
+
 pub struct StepForces(usize);
 
 impl StepForces {
@@ -5,11 +5,15 @@ pub struct ActivatorSystem;
 impl<'a> System<'a> for ActivatorSystem {
     type SystemData = (
         WriteStorage<'a, ::component::Activators>,
+        WriteStorage<'a, ::component::RhythmCoupling>,
         ReadExpect<'a, ::resource::UpdateTime>,
         WriteExpect<'a, ::resource::Tempos>,
     );
 
-    fn run(&mut self, (mut activatorses, update_time, mut tempos): Self::SystemData) {
+    fn run(
+        &mut self,
+        (mut activatorses, mut rhythm_couplings, update_time, mut tempos): Self::SystemData,
+    ) {
         for activator in (&mut activatorses).join().flat_map(|a| a.0.iter_mut()) {
             activator.activated = false;
         }
@@ -17,15 +21,34 @@ impl<'a> System<'a> for ActivatorSystem {
         for (id, tempo) in tempos.iter_mut().enumerate() {
             tempo.next_beat_time -= update_time.0;
             while tempo.next_beat_time <= 0.0 {
-                tempo.next_beat_time += tempo.time;
-                for activator in (&mut activatorses).join().flat_map(|a| a.0.iter_mut()) {
+                let beat_time = tempo.next_beat_time;
+                tempo.next_beat_time += tempo.time_at(tempo.beat);
+                for activator in (&mut activatorses, !&rhythm_couplings)
+                    .join()
+                    .flat_map(|(a, _)| a.0.iter_mut())
+                {
                     if activator.tempo == id {
-                        activator.activated =
-                            activator.partition[tempo.beat % activator.partition.len()];
+                        activator.activated = activator.is_activated_at(tempo.beat);
+                        activator.activated_time = beat_time;
                     }
                 }
                 tempo.beat += 1;
             }
         }
+
+        for (activators, coupling) in (&mut activatorses, &mut rhythm_couplings).join() {
+            coupling.next_beat_time -= update_time.0;
+            while coupling.next_beat_time <= 0.0 {
+                let beat_time = coupling.next_beat_time;
+                coupling.next_beat_time += coupling.current_time;
+                for activator in activators.iter_mut() {
+                    activator.activated = activator.is_activated_at(coupling.beat);
+                    activator.activated_time = beat_time;
+                }
+                coupling.beat += 1;
+                coupling.current_time +=
+                    (coupling.time - coupling.current_time) * coupling.period_coupling;
+            }
+        }
     }
 }
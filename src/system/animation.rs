@@ -1,4 +1,8 @@
-use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect, WriteStorage};
+use animation::{AnimationEvent, AnimationTrigger, EmittedEvent, Facing};
+use specs::{Entities, Join, ReadExpect, ReadStorage, System, WriteExpect, WriteStorage};
+
+/// Duration of the white flash when life decreases
+const FLASH_DURATION: f32 = 0.1;
 
 pub struct AnimationSystem;
 
@@ -6,9 +10,17 @@ impl<'a> System<'a> for AnimationSystem {
     type SystemData = (
         ReadStorage<'a, ::component::RigidBody>,
         WriteStorage<'a, ::component::AnimationState>,
+        ReadStorage<'a, ::component::Life>,
+        ReadStorage<'a, ::component::SwordRifle>,
+        ReadStorage<'a, ::component::Aim>,
         ReadExpect<'a, ::resource::UpdateTime>,
         ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::Visibility>,
         WriteExpect<'a, ::resource::AnimationImages>,
+        WriteExpect<'a, ::resource::DeathAnimations>,
+        WriteExpect<'a, ::resource::AnimationEvents>,
+        ReadStorage<'a, ::component::Visible>,
+        Entities<'a>,
     );
 
     fn run(
@@ -16,48 +28,174 @@ impl<'a> System<'a> for AnimationSystem {
         (
             rigid_bodies,
             mut animation_states,
+            lives,
+            sword_rifles,
+            aims,
             update_time,
             physic_world,
+            visibility,
             mut animation_images,
+            mut death_animations,
+            mut animation_events,
+            visibles,
+            entities,
         ): Self::SystemData,
 ){
-        for (state, body) in (&mut animation_states, &rigid_bodies).join() {
+        for (state, body, entity) in (&mut animation_states, &rigid_bodies, &*entities).join() {
             let body = body.get(&physic_world);
 
-            let velocity = body.velocity().linear.norm();
-            if velocity <= ::std::f32::EPSILON {
+            let velocity = body.velocity().linear;
+            let speed = velocity.norm();
+            if speed <= ::std::f32::EPSILON {
                 state.distance = 0.0;
             } else {
-                state.distance += update_time.0 * velocity;
+                state.distance += update_time.0 * speed;
             }
 
             state.timer += update_time.0;
+            state.flash = (state.flash - update_time.0).max(0.0);
+            if let Some(ref mut blend) = state.blend {
+                blend.timer += update_time.0;
+                blend.distance += update_time.0 * speed;
+                blend.remaining -= update_time.0;
+            }
+            if state.blend.as_ref().map(|blend| blend.remaining <= 0.0).unwrap_or(false) {
+                state.blend = None;
+            }
+
+            // Triggers
+            if let Some(life) = lives.get(entity) {
+                if state.last_life.map(|last| life.0 < last && life.0 > 0).unwrap_or(false) {
+                    state.trigger(AnimationTrigger::Hit);
+                    state.flash = FLASH_DURATION;
+                }
+                state.last_life = Some(life.0);
+            }
+            if let Some(sword_rifle) = sword_rifles.get(entity) {
+                if sword_rifle.sword_reloading > state.last_sword_reloading {
+                    state.trigger(AnimationTrigger::SwordAttack);
+                }
+                state.last_sword_reloading = sword_rifle.sword_reloading;
+            }
+
+            state.update_one_shot();
+            state.update_walking(speed);
 
-            // Remove finished animations
-            loop {
-                {
-                    let animation = state.animations.first();
-                    match animation {
-                        Some(animation) => if state.timer >= animation.duration {
-                            state.timer -= animation.duration;
-                        } else {
-                            break;
+            let body_angle = body.position().rotation.angle();
+            let facing = state
+                .state_machine
+                .as_ref()
+                .map(|state_machine| state_machine.facing)
+                .unwrap_or_default();
+            state.facing = match facing {
+                Facing::Body => body_angle,
+                Facing::Aim => aims.get(entity).map(|aim| aim.0).unwrap_or(body_angle),
+                Facing::Velocity => if speed > ::std::f32::EPSILON {
+                    velocity[1].atan2(velocity[0])
+                } else {
+                    state.facing
+                },
+            };
+
+            let position = ::na::Isometry2::new(body.position().translation.vector, state.facing);
+            let animation = state.animation();
+
+            // Events of the frames reached since the last update
+            let previous = (
+                state.timer - update_time.0,
+                (state.distance - update_time.0 * speed).max(0.0),
+            );
+            let footstep_sound = state
+                .state_machine
+                .as_ref()
+                .and_then(|state_machine| state_machine.footstep_sound.as_ref());
+            for part in &animation.parts {
+                for event in part.events_between(previous, (state.timer, state.distance)) {
+                    let event = match *event {
+                        AnimationEvent::Footstep => match footstep_sound {
+                            Some(sound) => AnimationEvent::PlaySound(sound.clone()),
+                            None => continue,
                         },
-                        None => break,
-                    }
+                        ref event => event.clone(),
+                    };
+                    animation_events.push(EmittedEvent {
+                        entity: Some(entity),
+                        position,
+                        event,
+                    });
                 }
-                state.animations.remove(0);
             }
 
-            let animation = state.animations.first().unwrap_or(&state.idle_animation);
+            if visibles.get(entity).is_none() {
+                continue;
+            }
 
+            let image_position = ::na::Isometry2::new(
+                body.position().translation.vector,
+                state.facing + state.rotation,
+            );
             for part in &animation.parts {
                 animation_images.push(::animation::AnimationImage {
-                    position: body.position(),
+                    position: image_position,
                     layer: part.layer,
-                    id: part.image_at(state.timer, state.distance),
+                    rect: part.rect_at(state.timer, state.distance),
+                    tint: state.tint,
+                    scale: state.scale,
+                    flash: state.flash / FLASH_DURATION,
                 });
             }
+            if let Some(ref blend) = state.blend {
+                let mut tint = state.tint;
+                tint[3] *= blend.remaining / blend.duration;
+                for part in &blend.animation.parts {
+                    animation_images.push(::animation::AnimationImage {
+                        position: image_position,
+                        layer: part.layer,
+                        rect: part.rect_at(blend.timer, blend.distance),
+                        tint,
+                        scale: state.scale,
+                        flash: state.flash / FLASH_DURATION,
+                    });
+                }
+            }
+        }
+
+        for death in death_animations.iter_mut() {
+            death.timer += update_time.0;
+            for part in &death.animation.parts {
+                for event in part.events_between((death.timer - update_time.0, 0.0), (death.timer, 0.0)) {
+                    if let AnimationEvent::Footstep = *event {
+                        continue;
+                    }
+                    animation_events.push(EmittedEvent {
+                        entity: None,
+                        position: death.position,
+                        event: event.clone(),
+                    });
+                }
+            }
+            let visible = visibility
+                .0
+                .as_ref()
+                .map(|polygon| polygon.contains(death.position.translation.vector))
+                .unwrap_or(true);
+            if visible && death.timer < death.animation.duration {
+                let image_position = ::na::Isometry2::new(
+                    death.position.translation.vector,
+                    death.position.rotation.angle() + death.rotation,
+                );
+                for part in &death.animation.parts {
+                    animation_images.push(::animation::AnimationImage {
+                        position: image_position,
+                        layer: part.layer,
+                        rect: part.rect_at(death.timer, 0.0),
+                        tint: death.tint,
+                        scale: death.scale,
+                        flash: 0.0,
+                    });
+                }
+            }
         }
+        death_animations.retain(|death| death.timer < death.animation.duration);
     }
 }
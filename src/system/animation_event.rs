@@ -0,0 +1,99 @@
+use animation::AnimationEvent;
+use entity::Insertable;
+use ncollide2d::query;
+use ncollide2d::shape::{Ball, ShapeHandle};
+use ncollide2d::world::CollisionGroups;
+use specs::{ReadExpect, ReadStorage, System, WriteExpect, WriteStorage};
+
+/// Play sounds, apply hitboxes and insert spawns of animation events
+pub struct AnimationEventSystem;
+
+impl<'a> System<'a> for AnimationEventSystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::RigidBody>,
+        ReadStorage<'a, ::component::ParticleEffects>,
+        WriteStorage<'a, ::component::Life>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::BodiesMap>,
+        ReadExpect<'a, ::resource::InsertablesMap>,
+        ReadExpect<'a, ::resource::LazyUpdate>,
+        ReadExpect<'a, ::resource::Audio>,
+        WriteExpect<'a, ::resource::Particles>,
+        WriteExpect<'a, ::resource::AnimationEvents>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            bodies,
+            particle_effects,
+            mut lives,
+            physic_world,
+            bodies_map,
+            insertables_map,
+            lazy_update,
+            audio,
+            mut particles,
+            mut animation_events,
+        ): Self::SystemData,
+    ) {
+        for emitted in animation_events.drain(..) {
+            match emitted.event {
+                AnimationEvent::PlaySound(ref sound) => {
+                    audio.play(sound, emitted.position.translation.vector, &physic_world);
+                }
+                AnimationEvent::Hitbox(ref hitbox) => {
+                    let position = emitted.position
+                        * ::na::Translation2::new(hitbox.distance, 0.0);
+                    let shape = ShapeHandle::new(Ball::new(hitbox.radius));
+                    let aabb = shape.aabb(&position);
+                    let mut groups = CollisionGroups::new();
+                    groups.set_whitelist(
+                        &hitbox
+                            .groups
+                            .iter()
+                            .map(|&group| group as usize)
+                            .collect::<Vec<_>>(),
+                    );
+
+                    let contacts = physic_world
+                        .collision_world()
+                        .interferences_with_aabb(&aabb, &groups)
+                        .filter(|obj| {
+                            query::contact(
+                                obj.position(),
+                                obj.shape().as_ref(),
+                                &position,
+                                shape.as_ref(),
+                                0.0,
+                            ).is_some()
+                        })
+                        .filter_map(|obj| bodies_map.get(&obj.data().body()).cloned())
+                        .filter(|&entity| Some(entity) != emitted.entity)
+                        .collect::<Vec<_>>();
+
+                    for contact in contacts {
+                        if let Some(life) = lives.get_mut(contact) {
+                            life.0 = life.0.saturating_sub(hitbox.damage);
+                            let effect = particle_effects
+                                .get(contact)
+                                .and_then(|effects| effects.damage.as_ref());
+                            if let (Some(effect), Some(body)) = (effect, bodies.get(contact)) {
+                                particles.emit(effect, *body.get(&physic_world).position());
+                            }
+                        }
+                    }
+                }
+                AnimationEvent::Spawn(ref insertable) => {
+                    if let Some(spawn) = insertables_map.get(insertable).cloned() {
+                        let position = emitted.position;
+                        lazy_update.exec(move |world| {
+                            spawn.insert(position.into(), world);
+                        });
+                    }
+                }
+                AnimationEvent::Footstep => (),
+            }
+        }
+    }
+}
@@ -16,12 +16,11 @@ impl<'a> ::specs::System<'a> for AudioSystem {
         (players, bodies, physic_world, save, mut audio): Self::SystemData,
     ) {
         // TODO: Fix it when multiple bodies
-        let position = (&players, &bodies).join().next().map(|(_, body)| {
-            body.get(&physic_world)
-                .position()
-                .translation.vector
+        let listener = (&players, &bodies).join().next().map(|(_, body)| {
+            let body = body.get(&physic_world);
+            (body.position().translation.vector, body.velocity().linear)
         });
 
-        audio.update(position, &save);
+        audio.update(listener, &save);
     }
 }
@@ -9,9 +9,14 @@ impl<'a> System<'a> for Boid {
         ReadStorage<'a, ::component::RigidBody>,
         ReadExpect<'a, ::resource::EntitiesRes>,
         WriteExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::AiDebug>,
+        WriteExpect<'a, ::resource::DebugShapes>,
     );
 
-    fn run(&mut self, (boids, bodies, entities, mut physic_world): Self::SystemData) {
+    fn run(
+        &mut self,
+        (boids, bodies, entities, mut physic_world, ai_debug, mut debug_shapes): Self::SystemData,
+    ) {
         for (boid, entity) in (&boids, &*entities).join() {
             let boids_direction = {
                 let body = bodies.get(entity);
@@ -25,6 +30,11 @@ impl<'a> System<'a> for Boid {
                     .vector;
                 let mut velocity = ::na::Vector2::new(0.0, 0.0);
 
+                if ai_debug.boid {
+                    debug_shapes.push_circle(position, boid.clamp.min_t);
+                    debug_shapes.push_circle(position, boid.clamp.max_t);
+                }
+
                 for (other_boid, other_body) in (&boids, &bodies).join() {
                     if other_boid.id == boid.id {
                         let other_body = other_body.get(&physic_world);
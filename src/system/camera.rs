@@ -1,18 +1,146 @@
+use graphics::CameraZoneMode;
 use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect};
 
-pub struct CameraSystem;
+/// The camera jumps to its target if it is further than this number of views
+const SNAP_VIEWS: f32 = 2.0;
+
+pub struct CameraSystem {
+    /// Position without shake
+    position: Option<::na::Vector2<f32>>,
+    velocity: ::na::Vector2<f32>,
+    time: f32,
+}
+
+impl CameraSystem {
+    pub fn new() -> Self {
+        CameraSystem {
+            position: None,
+            velocity: ::na::zero(),
+            time: 0.0,
+        }
+    }
+}
 
 impl<'a> System<'a> for CameraSystem {
     type SystemData = (
         ReadStorage<'a, ::component::RigidBody>,
-        ReadStorage<'a, ::component::Player>,
+        ReadStorage<'a, ::component::CameraTarget>,
+        ReadStorage<'a, ::component::Aim>,
         ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::UpdateTime>,
+        ReadExpect<'a, ::resource::WindowSize>,
+        ReadExpect<'a, ::resource::CameraZones>,
+        ReadExpect<'a, ::resource::Conf>,
         WriteExpect<'a, ::resource::Camera>,
     );
 
-    fn run(&mut self, (bodies, players, physic_world, mut camera): Self::SystemData) {
-        if let Some((_, body)) = (&players, &bodies).join().next() {
-            camera.position.translation = body.get(&physic_world).position().translation;
+    fn run(
+        &mut self,
+        (
+            bodies,
+            targets,
+            aims,
+            physic_world,
+            update_time,
+            window_size,
+            zones,
+            conf,
+            mut camera,
+        ): Self::SystemData,
+    ) {
+        let settings = &conf.camera;
+        let dt = update_time.0;
+        let ratio = (window_size.0).0 as f32 / (window_size.0).1.max(1) as f32;
+        // Half extents of the view for a zoom of 1
+        let view = if ratio > 1.0 {
+            ::na::Vector2::new(ratio, 1.0)
+        } else {
+            ::na::Vector2::new(1.0, 1.0 / ratio)
+        };
+
+        // Points to fit: targets and where they look ahead
+        let mut first_target = None;
+        let mut points = vec![];
+        let mut speed = 0f32;
+        for (target, body, aim) in (&targets, &bodies, aims.maybe()).join() {
+            let body = body.get(&physic_world);
+            let position = body.position().translation.vector;
+            first_target = first_target.or(Some(position));
+            points.push(position);
+            if let Some(aim) = aim {
+                points.push(position + ::na::Vector2::new(aim.cos(), aim.sin()) * target.look_ahead);
+            }
+            speed = speed.max(body.velocity().linear.norm());
         }
+        let first_target = match first_target {
+            Some(first_target) => first_target,
+            None => return,
+        };
+
+        let mut min = points[0];
+        let mut max = points[0];
+        for point in &points {
+            min = ::na::Vector2::new(min[0].min(point[0]), min[1].min(point[1]));
+            max = ::na::Vector2::new(max[0].max(point[0]), max[1].max(point[1]));
+        }
+        let mut target_position = (min + max) / 2.0;
+        let half_extents = (max - min) / 2.0 + ::na::Vector2::new(1.0, 1.0) * settings.fit_margin;
+        let mut target_zoom = (conf.zoom * settings.speed_zoom.compute(speed))
+            .max(half_extents[0] / view[0])
+            .max(half_extents[1] / view[1]);
+
+        if let Some(zone) = zones.iter().find(|zone| zone.contains(first_target)) {
+            let zone_center = (zone.min + zone.max) / 2.0;
+            let zone_half_extents = (zone.max - zone.min) / 2.0;
+            match zone.mode {
+                CameraZoneMode::Lock => {
+                    target_position = zone_center;
+                    target_zoom = (zone_half_extents[0] / view[0])
+                        .max(zone_half_extents[1] / view[1]);
+                }
+                CameraZoneMode::Bound => {
+                    target_zoom = target_zoom
+                        .min(zone_half_extents[0] / view[0])
+                        .min(zone_half_extents[1] / view[1]);
+                    for i in 0..2 {
+                        let margin = zone_half_extents[i] - view[i] * target_zoom;
+                        target_position[i] = target_position[i]
+                            .max(zone_center[i] - margin)
+                            .min(zone_center[i] + margin);
+                    }
+                }
+            }
+        }
+
+        // Spring smoothing
+        let position = match self.position {
+            Some(position)
+                if (target_position - position).norm() < SNAP_VIEWS * camera.zoom * view.norm() =>
+            {
+                let acceleration = (target_position - position) * settings.stiffness
+                    - self.velocity * settings.damping;
+                self.velocity += acceleration * dt;
+                position + self.velocity * dt
+            }
+            _ => {
+                self.velocity = ::na::zero();
+                camera.zoom = target_zoom;
+                target_position
+            }
+        };
+        self.position = Some(position);
+        camera.zoom += (target_zoom - camera.zoom) * (1.0 - (-settings.zoom_smoothing * dt).exp());
+
+        // Trauma shake
+        self.time += dt;
+        camera.trauma = (camera.trauma - settings.trauma_decay * dt).max(0.0);
+        let shake = camera.trauma.powi(2);
+        let t = self.time * settings.shake_frequency;
+        let noise = |seed: f32| ((t + seed).sin() + (2.3 * t + 1.7 * seed).sin()) / 2.0;
+        camera.position = ::na::Isometry2::new(
+            position
+                + ::na::Vector2::new(noise(0.0), noise(10.0)) * shake * settings.shake_translation,
+            noise(20.0) * shake * settings.shake_rotation,
+        );
     }
 }
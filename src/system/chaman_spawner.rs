@@ -39,7 +39,12 @@ impl<'a> System<'a> for ChamanSpawnerSystem {
                 if chaman_spawner.spawned.len() < chaman_spawner.number_of_spawn {
                     let spawn = insertables_map.get(&chaman_spawner.spawn).unwrap().clone();
                     let position = body.get(&physic_world).position().clone();
-                    audio.play(activator.sound, position.translation.vector.into());
+                    audio.play_at(
+                        &activator.sound,
+                        position.translation.vector.into(),
+                        activator.activated_time,
+                        &physic_world,
+                    );
                     lazy_update.exec(move |world| {
                         let spawned = spawn.insert(position.into(), world);
                         if let Some(chaman_spawner) = world
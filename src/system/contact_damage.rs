@@ -1,4 +1,4 @@
-use specs::{Join, ReadStorage, System, WriteStorage};
+use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect, WriteStorage};
 
 pub struct ContactDamageSystem;
 
@@ -7,15 +7,44 @@ impl<'a> System<'a> for ContactDamageSystem {
         ReadStorage<'a, ::component::Player>,
         ReadStorage<'a, ::component::Contactor>,
         ReadStorage<'a, ::component::ContactDamage>,
+        ReadStorage<'a, ::component::RigidBody>,
+        ReadStorage<'a, ::component::ParticleEffects>,
         WriteStorage<'a, ::component::Life>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        WriteExpect<'a, ::resource::Particles>,
+        WriteExpect<'a, ::resource::Camera>,
+        ReadExpect<'a, ::resource::Conf>,
     );
 
-    fn run(&mut self, (players, contactors, damages, mut lives): Self::SystemData) {
+    fn run(
+        &mut self,
+        (
+            players,
+            contactors,
+            damages,
+            bodies,
+            particle_effects,
+            mut lives,
+            physic_world,
+            mut particles,
+            mut camera,
+            conf,
+        ): Self::SystemData,
+    ) {
         for (damage, contactor) in (&damages, &contactors).join() {
             if !contactor.0.is_empty() {
                 for &contact in &contactor.0 {
                     match (players.get(contact), lives.get_mut(contact)) {
-                        (Some(_), Some(life)) => life.0 -= damage.0,
+                        (Some(_), Some(life)) => {
+                            life.0 -= damage.0;
+                            camera.add_trauma(conf.camera.damage_trauma);
+                            let effect = particle_effects
+                                .get(contact)
+                                .and_then(|effects| effects.damage.as_ref());
+                            if let (Some(effect), Some(body)) = (effect, bodies.get(contact)) {
+                                particles.emit(effect, *body.get(&physic_world).position());
+                            }
+                        }
                         _ => (),
                     }
                 }
@@ -0,0 +1,88 @@
+use specs::{Entity, Join, ReadExpect, ReadStorage, System, WriteExpect};
+use std::collections::HashMap;
+
+/// Duration of a damage number
+const DAMAGE_TEXT_LIFETIME: f32 = 0.8;
+/// Velocity of a damage number in world units per second
+const DAMAGE_TEXT_RISE: f32 = 3.0;
+/// Size in world units
+const DAMAGE_TEXT_SIZE: f32 = 1.0;
+const DAMAGE_TEXT_COLOR: [f32; 4] = [1.0, 0.9, 0.2, 1.0];
+const DAMAGE_TEXT_OUTLINE: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+
+struct DamageText {
+    damage: usize,
+    position: ::na::Vector2<f32>,
+    age: f32,
+}
+
+/// Damage numbers rising from entities whose life decreases
+pub struct DamageTextSystem {
+    lives: HashMap<Entity, usize>,
+    texts: Vec<DamageText>,
+}
+
+impl DamageTextSystem {
+    pub fn new() -> Self {
+        DamageTextSystem {
+            lives: HashMap::new(),
+            texts: vec![],
+        }
+    }
+}
+
+impl<'a> System<'a> for DamageTextSystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::Life>,
+        ReadStorage<'a, ::component::RigidBody>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::UpdateTime>,
+        ReadExpect<'a, ::resource::EntitiesRes>,
+        WriteExpect<'a, ::resource::Texts>,
+    );
+
+    fn run(
+        &mut self,
+        (lives, bodies, physic_world, update_time, entities, mut texts): Self::SystemData,
+    ) {
+        let mut new_lives = HashMap::new();
+        for (life, body, entity) in (&lives, &bodies, &*entities).join() {
+            if let Some(&previous) = self.lives.get(&entity) {
+                if life.0 < previous {
+                    self.texts.push(DamageText {
+                        damage: previous - life.0,
+                        position: body.get(&physic_world).position().translation.vector,
+                        age: 0.0,
+                    });
+                }
+            }
+            new_lives.insert(entity, life.0);
+        }
+        self.lives = new_lives;
+
+        for text in &mut self.texts {
+            text.age += update_time.0;
+            text.position[1] += DAMAGE_TEXT_RISE * update_time.0;
+        }
+        self.texts.retain(|text| text.age < DAMAGE_TEXT_LIFETIME);
+
+        for text in &self.texts {
+            let alpha = 1.0 - text.age / DAMAGE_TEXT_LIFETIME;
+            let mut color = DAMAGE_TEXT_COLOR;
+            color[3] *= alpha;
+            let mut outline = DAMAGE_TEXT_OUTLINE;
+            outline[3] *= alpha;
+            texts.push(::text::Text {
+                color,
+                outline: Some(outline),
+                align: ::text::Align::Center,
+                ..::text::Text::new(
+                    format!("-{}", text.damage),
+                    text.position,
+                    ::text::Space::World,
+                    DAMAGE_TEXT_SIZE,
+                )
+            });
+        }
+    }
+}
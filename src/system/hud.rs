@@ -0,0 +1,231 @@
+use hud::HudShape;
+use specs::{Entity, Join, ReadExpect, ReadStorage, System, WriteExpect};
+use text::{Align, Space, Text};
+
+const MARGIN: f32 = 30.0;
+const TEXT_SIZE: f32 = 20.0;
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const TEXT_OUTLINE: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+
+const LIFE_BAR_SIZE: [f32; 2] = [200.0, 16.0];
+const LIFE_COLOR: [f32; 4] = [0.8, 0.1, 0.1, 1.0];
+
+const WEAPON_RADIUS: f32 = 36.0;
+const RELOAD_RING_WIDTH: f32 = 5.0;
+const ACTIVE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const INACTIVE_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.6];
+
+/// Beats displayed by the metronome of each tempo
+const METRONOME_BEATS: usize = 4;
+const METRONOME_RADIUS: f32 = 8.0;
+const METRONOME_SPACING: f32 = 28.0;
+/// Time for the flash of a beat to fade by e
+const METRONOME_FLASH_DECAY: f32 = 0.08;
+const METRONOME_COLOR: [f32; 4] = [0.4, 0.4, 0.4, 0.8];
+const METRONOME_FLASH_COLOR: [f32; 4] = [1.0, 0.9, 0.3, 1.0];
+
+fn lerp_color(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+        a[3] + (b[3] - a[3]) * t,
+    ]
+}
+
+fn hud_text(string: String, position: [f32; 2], align: Align) -> Text {
+    Text {
+        color: TEXT_COLOR,
+        outline: Some(TEXT_OUTLINE),
+        align,
+        ..Text::new(
+            string,
+            ::na::Vector2::new(position[0], position[1]),
+            Space::Screen,
+            TEXT_SIZE,
+        )
+    }
+}
+
+/// Life bar, weapon mode with its reloads and metronome of tempos
+pub struct HudSystem {
+    /// Maximal life seen for the player
+    max_life: Option<(Entity, usize)>,
+}
+
+impl HudSystem {
+    pub fn new() -> Self {
+        HudSystem { max_life: None }
+    }
+}
+
+impl<'a> System<'a> for HudSystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::Player>,
+        ReadStorage<'a, ::component::Life>,
+        ReadStorage<'a, ::component::SwordRifle>,
+        ReadExpect<'a, ::resource::EntitiesRes>,
+        ReadExpect<'a, ::resource::Tempos>,
+        ReadExpect<'a, ::resource::WindowSize>,
+        WriteExpect<'a, ::resource::HudShapes>,
+        WriteExpect<'a, ::resource::Texts>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            players,
+            lives,
+            sword_rifles,
+            entities,
+            tempos,
+            window_size,
+            mut shapes,
+            mut texts,
+        ): Self::SystemData,
+    ) {
+        let (width, height) = ((window_size.0).0 as f32, (window_size.0).1 as f32);
+
+        // Life bar
+        if let Some((_, life, entity)) = (&players, &lives, &*entities).join().next() {
+            let max_life = match self.max_life {
+                Some((max_entity, max_life)) if max_entity == entity => max_life.max(life.0),
+                _ => life.0,
+            };
+            self.max_life = Some((entity, max_life));
+
+            let min = [MARGIN, MARGIN];
+            let max = [MARGIN + LIFE_BAR_SIZE[0], MARGIN + LIFE_BAR_SIZE[1]];
+            shapes.push(HudShape::Rectangle {
+                min,
+                max,
+                color: BACKGROUND_COLOR,
+            });
+            if max_life > 0 {
+                shapes.push(HudShape::Rectangle {
+                    min,
+                    max: [
+                        min[0] + LIFE_BAR_SIZE[0] * life.0 as f32 / max_life as f32,
+                        max[1],
+                    ],
+                    color: LIFE_COLOR,
+                });
+            }
+            texts.push(hud_text(
+                format!("{}/{}", life.0, max_life),
+                [max[0] + MARGIN / 2.0, (min[1] + max[1]) / 2.0],
+                Align::Left,
+            ));
+        } else {
+            self.max_life = None;
+        }
+
+        // Weapon mode and reloads
+        if let Some((_, sword_rifle)) = (&players, &sword_rifles).join().next() {
+            let center = [MARGIN + WEAPON_RADIUS, height - MARGIN - WEAPON_RADIUS];
+            shapes.push(HudShape::Disk {
+                center,
+                radius: WEAPON_RADIUS,
+                color: BACKGROUND_COLOR,
+            });
+
+            let icon = WEAPON_RADIUS / 2.0;
+            if sword_rifle.sword_mode {
+                shapes.push(HudShape::Rectangle {
+                    min: [center[0] - icon / 6.0, center[1] - icon],
+                    max: [center[0] + icon / 6.0, center[1] + icon / 2.0],
+                    color: ACTIVE_COLOR,
+                });
+                shapes.push(HudShape::Rectangle {
+                    min: [center[0] - icon / 2.0, center[1] + icon / 2.0],
+                    max: [center[0] + icon / 2.0, center[1] + icon * 0.7],
+                    color: ACTIVE_COLOR,
+                });
+            } else {
+                shapes.push(HudShape::Rectangle {
+                    min: [center[0] - icon, center[1] - icon / 6.0],
+                    max: [center[0] + icon, center[1] + icon / 6.0],
+                    color: ACTIVE_COLOR,
+                });
+                shapes.push(HudShape::Rectangle {
+                    min: [center[0] - icon, center[1]],
+                    max: [center[0] - icon / 2.0, center[1] + icon * 0.7],
+                    color: ACTIVE_COLOR,
+                });
+            }
+
+            let reloads = [
+                (
+                    sword_rifle.sword_mode,
+                    sword_rifle.sword_reloading,
+                    sword_rifle.sword_reload_time,
+                ),
+                (
+                    !sword_rifle.sword_mode,
+                    sword_rifle.rifle_reloading,
+                    sword_rifle.rifle_reload_time,
+                ),
+            ];
+            for (i, &(active, reloading, reload_time)) in reloads.iter().enumerate() {
+                let progress = if reload_time > 0.0 {
+                    1.0 - reloading.max(0.0) / reload_time
+                } else {
+                    1.0
+                };
+                shapes.push(HudShape::Ring {
+                    center,
+                    radius: WEAPON_RADIUS + (i as f32 + 1.0) * RELOAD_RING_WIDTH * 1.5,
+                    width: RELOAD_RING_WIDTH,
+                    progress,
+                    color: if active { ACTIVE_COLOR } else { INACTIVE_COLOR },
+                });
+            }
+
+            texts.push(hud_text(
+                if sword_rifle.sword_mode { "sword" } else { "rifle" }.to_string(),
+                [center[0] + WEAPON_RADIUS * 1.5 + MARGIN / 2.0, center[1]],
+                Align::Left,
+            ));
+        }
+
+        // Metronome
+        for (i, tempo) in tempos.iter().enumerate() {
+            let y = MARGIN + METRONOME_RADIUS + i as f32 * METRONOME_SPACING;
+            let first_x = width / 2.0 - (METRONOME_BEATS - 1) as f32 * METRONOME_SPACING / 2.0;
+            let last_beat = tempo.beat.checked_sub(1);
+            let flash = last_beat
+                .map(|beat| {
+                    let elapsed = tempo.time_at(beat) - tempo.next_beat_time;
+                    (-elapsed.max(0.0) / METRONOME_FLASH_DECAY).exp()
+                })
+                .unwrap_or(0.0);
+
+            for j in 0..METRONOME_BEATS {
+                let current = last_beat.map(|beat| beat % METRONOME_BEATS == j).unwrap_or(false);
+                let (color, radius) = if current {
+                    (
+                        lerp_color(METRONOME_FLASH_COLOR, ACTIVE_COLOR, 1.0 - flash),
+                        METRONOME_RADIUS * (1.0 + flash / 2.0),
+                    )
+                } else {
+                    (METRONOME_COLOR, METRONOME_RADIUS)
+                };
+                shapes.push(HudShape::Disk {
+                    center: [first_x + j as f32 * METRONOME_SPACING, y],
+                    radius,
+                    color,
+                });
+            }
+
+            texts.push(hud_text(
+                format!("{}", tempo.beat),
+                [
+                    first_x + METRONOME_BEATS as f32 * METRONOME_SPACING,
+                    y,
+                ],
+                Align::Left,
+            ));
+        }
+    }
+}
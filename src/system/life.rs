@@ -1,16 +1,55 @@
-use specs::{Join, ReadExpect, ReadStorage, System};
+use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect};
 
 pub struct LifeSystem;
 
 impl<'a> System<'a> for LifeSystem {
     type SystemData = (
         ReadStorage<'a, ::component::Life>,
+        ReadStorage<'a, ::component::RigidBody>,
+        ReadStorage<'a, ::component::ParticleEffects>,
+        ReadStorage<'a, ::component::AnimationState>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        WriteExpect<'a, ::resource::Particles>,
+        WriteExpect<'a, ::resource::DeathAnimations>,
         ReadExpect<'a, ::resource::EntitiesRes>,
     );
 
-    fn run(&mut self, (lives, entities): Self::SystemData) {
+    fn run(
+        &mut self,
+        (
+            lives,
+            bodies,
+            particle_effects,
+            animation_states,
+            physic_world,
+            mut particles,
+            mut death_animations,
+            entities,
+        ): Self::SystemData,
+    ) {
         for (life, entity) in (&lives, &*entities).join() {
             if life.0 <= 0 {
+                let death = particle_effects
+                    .get(entity)
+                    .and_then(|effects| effects.death.as_ref());
+                if let (Some(death), Some(body)) = (death, bodies.get(entity)) {
+                    particles.emit(death, *body.get(&physic_world).position());
+                }
+                if let (Some(state), Some(body)) = (animation_states.get(entity), bodies.get(entity)) {
+                    if let Some(animation) = state.death_animation() {
+                        death_animations.push(::animation::DeathAnimation {
+                            position: ::na::Isometry2::new(
+                                body.get(&physic_world).position().translation.vector,
+                                state.facing,
+                            ),
+                            animation,
+                            timer: 0.0,
+                            tint: state.tint,
+                            scale: state.scale,
+                            rotation: state.rotation,
+                        });
+                    }
+                }
                 entities.delete(entity).unwrap();
             }
         }
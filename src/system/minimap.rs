@@ -0,0 +1,185 @@
+use hud::{Corner, HudShape};
+use ncollide2d::shape::Segment;
+use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect};
+
+const MARGIN: f32 = 30.0;
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+const WALL_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+const WALL_WIDTH: f32 = 2.0;
+const PLAYER_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const AIM_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.3];
+const ENTITY_COLOR: [f32; 4] = [1.0, 0.3, 0.2, 1.0];
+const DOT_RADIUS: f32 = 3.0;
+
+/// Clip the segment to the square [min, max]², none if it is outside
+fn clip(a: [f32; 2], b: [f32; 2], min: f32, max: f32) -> Option<([f32; 2], [f32; 2])> {
+    let d = [b[0] - a[0], b[1] - a[1]];
+    let (mut t0, mut t1) = (0f32, 1f32);
+    for i in 0..2 {
+        for &(p, q) in &[(-d[i], a[i] - min), (d[i], max - a[i])] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else {
+                let t = q / p;
+                if p < 0.0 {
+                    t0 = t0.max(t);
+                } else {
+                    t1 = t1.min(t);
+                }
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    Some((
+        [a[0] + d[0] * t0, a[1] + d[1] * t0],
+        [a[0] + d[0] * t1, a[1] + d[1] * t1],
+    ))
+}
+
+/// Walls, player with its aim and entities of groups around the player
+pub struct MinimapSystem;
+
+impl<'a> System<'a> for MinimapSystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::Player>,
+        ReadStorage<'a, ::component::RigidBody>,
+        ReadStorage<'a, ::component::Aim>,
+        ReadStorage<'a, ::component::Visible>,
+        ReadStorage<'a, ::component::Revealed>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::BodiesMap>,
+        ReadExpect<'a, ::resource::WindowSize>,
+        ReadExpect<'a, ::resource::Conf>,
+        WriteExpect<'a, ::resource::HudShapes>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            players,
+            bodies,
+            aims,
+            visibles,
+            revealeds,
+            physic_world,
+            bodies_map,
+            window_size,
+            conf,
+            mut shapes,
+        ): Self::SystemData,
+    ) {
+        let settings = match conf.minimap {
+            Some(ref settings) => settings,
+            None => return,
+        };
+        let (player_position, aim) = match (&players, &bodies, aims.maybe()).join().next() {
+            Some((_, body, aim)) => (
+                body.get(&physic_world).position().translation.vector,
+                aim.map(|aim| aim.0),
+            ),
+            None => return,
+        };
+
+        let (width, height) = ((window_size.0).0 as f32, (window_size.0).1 as f32);
+        let min = match settings.corner {
+            Corner::TopLeft | Corner::BottomLeft => MARGIN,
+            Corner::TopRight | Corner::BottomRight => width - MARGIN - settings.size,
+        };
+        let top = match settings.corner {
+            Corner::TopLeft | Corner::TopRight => MARGIN,
+            Corner::BottomLeft | Corner::BottomRight => height - MARGIN - settings.size,
+        };
+        let half = settings.size / 2.0;
+        let center = [min + half, top + half];
+
+        // Position relative to the center in pixels, y down
+        let to_minimap = |position: ::na::Vector2<f32>| {
+            let relative = (position - player_position) / settings.zoom;
+            [relative[0], -relative[1]]
+        };
+        let to_screen = |p: [f32; 2]| [center[0] + p[0], center[1] + p[1]];
+
+        shapes.push(HudShape::Rectangle {
+            min: [min, top],
+            max: [min + settings.size, top + settings.size],
+            color: BACKGROUND_COLOR,
+        });
+
+        for collider in physic_world.colliders() {
+            if !collider
+                .collision_groups()
+                .is_member_of(::entity::Group::Wall as usize)
+            {
+                continue;
+            }
+            if let Some(segment) = collider.shape().as_shape::<Segment<f32>>() {
+                let a = to_minimap((collider.position() * *segment.a()).coords);
+                let b = to_minimap((collider.position() * *segment.b()).coords);
+                if let Some((a, b)) = clip(a, b, -half, half) {
+                    shapes.push(HudShape::Segment {
+                        a: to_screen(a),
+                        b: to_screen(b),
+                        width: WALL_WIDTH,
+                        color: WALL_COLOR,
+                    });
+                }
+            }
+        }
+
+        for collider in physic_world.colliders() {
+            let groups = collider.collision_groups();
+            if !settings
+                .groups
+                .iter()
+                .any(|&group| groups.is_member_of(group as usize))
+            {
+                continue;
+            }
+            let entity = match bodies_map.get(&collider.data().body()) {
+                Some(&entity) => entity,
+                None => continue,
+            };
+            let shown = revealeds.get(entity).is_some()
+                || !settings.line_of_sight
+                || visibles.get(entity).is_some();
+            if !shown || players.get(entity).is_some() {
+                continue;
+            }
+            let p = to_minimap(collider.position().translation.vector);
+            if p[0].abs() > half || p[1].abs() > half {
+                continue;
+            }
+            shapes.push(HudShape::Disk {
+                center: to_screen(p),
+                radius: DOT_RADIUS,
+                color: ENTITY_COLOR,
+            });
+        }
+
+        if let Some(aim) = aim {
+            let point = |angle: f32| {
+                to_screen([
+                    angle.cos() * settings.aim_length,
+                    -angle.sin() * settings.aim_length,
+                ])
+            };
+            shapes.push(HudShape::Triangle {
+                points: [
+                    center,
+                    point(aim - settings.aim_cone / 2.0),
+                    point(aim + settings.aim_cone / 2.0),
+                ],
+                color: AIM_COLOR,
+            });
+        }
+        shapes.push(HudShape::Disk {
+            center,
+            radius: DOT_RADIUS,
+            color: PLAYER_COLOR,
+        });
+    }
+}
@@ -46,11 +46,38 @@ pub use self::velocity_dampings::*;
 mod activator;
 pub use self::activator::*;
 
+mod rhythm_coupling;
+pub use self::rhythm_coupling::*;
+
 mod sword_rifle;
 pub use self::sword_rifle::*;
 
 mod audio;
 pub use self::audio::*;
 
+mod music;
+pub use self::music::*;
+
+mod sound_emitter;
+pub use self::sound_emitter::*;
+
+mod visibility;
+pub use self::visibility::*;
+
 mod position_in_path;
 pub use self::position_in_path::*;
+
+mod particle;
+pub use self::particle::*;
+
+mod damage_text;
+pub use self::damage_text::*;
+
+mod hud;
+pub use self::hud::*;
+
+mod animation_event;
+pub use self::animation_event::*;
+
+mod minimap;
+pub use self::minimap::*;
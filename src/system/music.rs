@@ -0,0 +1,35 @@
+use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect};
+
+pub struct MusicSystem;
+
+impl<'a> System<'a> for MusicSystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::Player>,
+        ReadStorage<'a, ::component::RigidBody>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::Tempos>,
+        ReadExpect<'a, ::resource::Save>,
+        ReadExpect<'a, ::resource::Audio>,
+        WriteExpect<'a, ::resource::Music>,
+    );
+
+    fn run(
+        &mut self,
+        (players, bodies, physic_world, tempos, save, audio, mut music): Self::SystemData,
+    ) {
+        let intensity = match music.intensity() {
+            Some(intensity) => {
+                // TODO: Fix it when multiple bodies
+                let position = (&players, &bodies).join().next().map(|(_, body)| {
+                    body.get(&physic_world)
+                        .position()
+                        .translation.vector
+                });
+                intensity.compute(position, &physic_world)
+            }
+            None => return,
+        };
+
+        music.update(intensity, &tempos[0], save.audio_volume, &audio);
+    }
+}
@@ -0,0 +1,26 @@
+use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect};
+
+pub struct ParticleSystem;
+
+impl<'a> System<'a> for ParticleSystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::RigidBody>,
+        ReadStorage<'a, ::component::ParticleEffects>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::UpdateTime>,
+        WriteExpect<'a, ::resource::Particles>,
+    );
+
+    fn run(
+        &mut self,
+        (bodies, particle_effects, physic_world, update_time, mut particles): Self::SystemData,
+    ) {
+        for (effects, body) in (&particle_effects, &bodies).join() {
+            if let Some(ref ambient) = effects.ambient {
+                let position = *body.get(&physic_world).position();
+                particles.emit_continuous(ambient, position, update_time.0);
+            }
+        }
+        particles.update(update_time.0);
+    }
+}
@@ -9,9 +9,11 @@ impl<'a> System<'a> for PositionInPathSystem {
         WriteStorage<'a, ::component::PositionInPath>,
         ReadExpect<'a, ::resource::UpdateTime>,
         WriteExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::AiDebug>,
+        WriteExpect<'a, ::resource::DebugShapes>,
     );
 
-    fn run(&mut self, (bodies, mut paths, update_time, mut physic_world): Self::SystemData) {
+    fn run(&mut self, (bodies, mut paths, update_time, mut physic_world, ai_debug, mut debug_shapes): Self::SystemData) {
         for (path, body) in (&mut paths, &bodies).join() {
             path.current_advancement += update_time.0*path.velocity;
             while path.current_advancement >= path.distances[path.current_point] {
@@ -24,6 +26,11 @@ impl<'a> System<'a> for PositionInPathSystem {
             let displacment = path.points[path.current_point] + path.current_advancement*line - body.position().translation.vector;
             body.apply_displacement(&Velocity2::new(displacment, 0.0));
             body.set_velocity(Velocity2::zero());
+
+            if ai_debug.position_in_path {
+                debug_shapes.push_polyline(&path.points, true);
+                debug_shapes.push_marker(path.points[(path.current_point + 1)%path.points.len()]);
+            }
         }
     }
 }
@@ -0,0 +1,57 @@
+use specs::{Join, ReadExpect, ReadStorage, System, WriteStorage};
+
+pub struct RhythmCouplingSystem;
+
+impl<'a> System<'a> for RhythmCouplingSystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::Activators>,
+        ReadStorage<'a, ::component::RigidBody>,
+        WriteStorage<'a, ::component::RhythmCoupling>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::EntitiesRes>,
+    );
+
+    fn run(
+        &mut self,
+        (activatorses, bodies, mut rhythm_couplings, physic_world, entities): Self::SystemData,
+    ) {
+        let activated = (&rhythm_couplings, &activatorses, &bodies, &*entities)
+            .join()
+            .filter(|(coupling, activators, _, _)| activators[coupling.activator].activated)
+            .map(|(coupling, _, body, entity)| {
+                (
+                    entity,
+                    body.get(&physic_world).position().translation.vector,
+                    coupling.current_time,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        if activated.is_empty() {
+            return;
+        }
+
+        for (coupling, activators, body, entity) in
+            (&mut rhythm_couplings, &activatorses, &bodies, &*entities).join()
+        {
+            let position = body.get(&physic_world).position().translation.vector;
+            let mut shift = 0.0;
+            let mut time_shift = 0.0;
+            for &(other_entity, ref other_position, other_time) in &activated {
+                if other_entity == entity {
+                    continue;
+                }
+                let strength = coupling.coupling.compute((other_position - position).norm());
+                if let Some(distance) =
+                    coupling.distance_to_closest(activators[coupling.activator].beats())
+                {
+                    shift += strength * distance;
+                }
+                time_shift +=
+                    strength * coupling.period_coupling * (other_time - coupling.current_time);
+            }
+            coupling.shift_time(time_shift);
+            coupling.shift(shift);
+        }
+    }
+}
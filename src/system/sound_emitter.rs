@@ -0,0 +1,37 @@
+use specs::{Join, ReadExpect, ReadStorage, System, WriteStorage};
+
+pub struct SoundEmitterSystem;
+
+impl<'a> System<'a> for SoundEmitterSystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::RigidBody>,
+        WriteStorage<'a, ::component::SoundEmitter>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::Audio>,
+    );
+
+    fn run(&mut self, (bodies, mut sound_emitters, physic_world, audio): Self::SystemData) {
+        for (sound_emitter, body) in (&mut sound_emitters, &bodies).join() {
+            let body = body.get(&physic_world);
+            let position = body.position().translation.vector;
+            let velocity = if sound_emitter.doppler {
+                Some(body.velocity().linear)
+            } else {
+                None
+            };
+
+            if let Some(ref control) = sound_emitter.control {
+                audio.update_emitter(control, position, velocity, &physic_world);
+            } else if !sound_emitter.started {
+                sound_emitter.started = true;
+                sound_emitter.control = audio.play_emitter(
+                    &sound_emitter.sound,
+                    sound_emitter.looping,
+                    position,
+                    velocity,
+                    &physic_world,
+                );
+            }
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use animation::AnimationTrigger;
 use entity::Group;
 use ncollide2d::query::{self, Ray};
 use ncollide2d::world::CollisionGroups;
-use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect, WriteStorage};
+use specs::{Entity, Join, ReadExpect, ReadStorage, System, WriteExpect, WriteStorage};
 
 pub struct SwordRifleSystem;
 
@@ -9,12 +10,18 @@ impl<'a> System<'a> for SwordRifleSystem {
     type SystemData = (
         ReadStorage<'a, ::component::RigidBody>,
         ReadStorage<'a, ::component::Aim>,
+        ReadStorage<'a, ::component::ParticleEffects>,
+        ReadStorage<'a, ::component::AnimationState>,
+        ReadExpect<'a, ::resource::EntitiesRes>,
         WriteStorage<'a, ::component::SwordRifle>,
         WriteStorage<'a, ::component::Life>,
         ReadExpect<'a, ::resource::PhysicWorld>,
         ReadExpect<'a, ::resource::BodiesMap>,
         ReadExpect<'a, ::resource::UpdateTime>,
         WriteExpect<'a, ::resource::DebugShapes>,
+        WriteExpect<'a, ::resource::Particles>,
+        WriteExpect<'a, ::resource::Camera>,
+        ReadExpect<'a, ::resource::Conf>,
     );
 
     fn run(
@@ -22,15 +29,31 @@ impl<'a> System<'a> for SwordRifleSystem {
         (
             bodies,
             aims,
+            particle_effects,
+            animation_states,
+            entities,
             mut sword_rifles,
             mut lives,
             physic_world,
             bodies_map,
             update_time,
             mut debug_shapes,
+            mut particles,
+            mut camera,
+            conf,
         ): Self::SystemData,
     ) {
-        for (sr, aim, body) in (&mut sword_rifles, &aims, &bodies).join() {
+        let damage_effect = |entity: Entity, particles: &mut ::resource::Particles| {
+            let effect = particle_effects
+                .get(entity)
+                .and_then(|effects| effects.damage.as_ref());
+            if let (Some(effect), Some(body)) = (effect, bodies.get(entity)) {
+                particles.emit(effect, *body.get(&physic_world).position());
+            }
+        };
+
+        for (sr, aim, body, entity) in (&mut sword_rifles, &aims, &bodies, &*entities).join() {
+            let effects = particle_effects.get(entity);
             sr.sword_reloading -= update_time.0;
             sr.rifle_reloading -= update_time.0;
 
@@ -45,26 +68,39 @@ impl<'a> System<'a> for SwordRifleSystem {
                         let mut groups = CollisionGroups::new();
                         groups.set_whitelist(&[::entity::Group::Monster as usize]);
 
-                        let contacts = physic_world
-                            .collision_world()
-                            .interferences_with_aabb(&aabb, &groups)
-                            .filter(|obj| {
-                                query::contact(
-                                    obj.position(),
-                                    obj.shape().as_ref(),
-                                    &position,
-                                    sr.sword_shape.as_ref(),
-                                    0.0,
-                                ).is_some()
-                            })
-                            .map(|obj| bodies_map.get(&obj.data().body()).unwrap());
+                        // Damage is dealt by the hitbox event of the attack animation if any
+                        let animated = animation_states
+                            .get(entity)
+                            .map(|state| state.has_hitbox(AnimationTrigger::SwordAttack))
+                            .unwrap_or(false);
 
-                        for contact in contacts {
-                            if let Some(ref mut life) = lives.get_mut(*contact) {
-                                life.0 -= sr.sword_damage;
+                        if !animated {
+                            let contacts = physic_world
+                                .collision_world()
+                                .interferences_with_aabb(&aabb, &groups)
+                                .filter(|obj| {
+                                    query::contact(
+                                        obj.position(),
+                                        obj.shape().as_ref(),
+                                        &position,
+                                        sr.sword_shape.as_ref(),
+                                        0.0,
+                                    ).is_some()
+                                })
+                                .map(|obj| bodies_map.get(&obj.data().body()).unwrap());
+
+                            for contact in contacts {
+                                if let Some(ref mut life) = lives.get_mut(*contact) {
+                                    life.0 -= sr.sword_damage;
+                                    damage_effect(*contact, &mut particles);
+                                    camera.add_trauma(conf.camera.sword_trauma);
+                                }
                             }
                         }
 
+                        if let Some(sword) = effects.and_then(|effects| effects.sword.as_ref()) {
+                            particles.emit(sword, position);
+                        }
                         debug_shapes.push((position, sr.sword_shape.clone()));
                     }
                 } else {
@@ -81,17 +117,29 @@ impl<'a> System<'a> for SwordRifleSystem {
                         let mut groups = CollisionGroups::new();
                         groups.set_whitelist(&[Group::Monster as usize, Group::Wall as usize]);
 
-                        let contacts = physic_world
+                        let hit = physic_world
                             .collision_world()
                             .interferences_with_ray(&ray, &groups)
                             .min_by_key(|(_, intersection)| {
                                 (intersection.toi * ::CMP_PRECISION) as isize
-                            })
-                            .map(|(obj, _)| bodies_map.get(&obj.data().body()).unwrap());
+                            });
+
+                        if let Some((obj, intersection)) = hit {
+                            if let Some(impact) = effects.and_then(|effects| effects.impact.as_ref()) {
+                                let normal = intersection.normal;
+                                particles.emit(
+                                    impact,
+                                    ::na::Isometry2::new(
+                                        ray.origin.coords + ray.dir * intersection.toi,
+                                        normal[1].atan2(normal[0]),
+                                    ),
+                                );
+                            }
 
-                        for contact in contacts {
+                            let contact = bodies_map.get(&obj.data().body()).unwrap();
                             if let Some(ref mut life) = lives.get_mut(*contact) {
                                 life.0 -= sr.rifle_damage;
+                                damage_effect(*contact, &mut particles);
                             }
                         }
                     }
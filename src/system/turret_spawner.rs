@@ -1,7 +1,10 @@
 use entity::Insertable;
-use specs::{Join, ReadExpect, ReadStorage, WriteStorage, System};
+use specs::{Join, ReadExpect, ReadStorage, WriteExpect, WriteStorage, System};
 use std::f32::consts::PI;
 
+/// Length of the debug ray of the firing angle beyond the shoot distance
+const DEBUG_FIRING_RAY_LENGTH: f32 = 5.0;
+
 pub struct TurretSpawnerSystem;
 
 impl<'a> System<'a> for TurretSpawnerSystem {
@@ -14,11 +17,13 @@ impl<'a> System<'a> for TurretSpawnerSystem {
         ReadExpect<'a, ::resource::LazyUpdate>,
         ReadExpect<'a, ::resource::InsertablesMap>,
         ReadExpect<'a, ::resource::Audio>,
+        ReadExpect<'a, ::resource::AiDebug>,
+        WriteExpect<'a, ::resource::DebugShapes>,
     );
 
     fn run(
         &mut self,
-        (bodies, activatorses, mut turret_spawners, physic_world, tempos, lazy_update, insertables_map, audio): Self::SystemData,
+        (bodies, activatorses, mut turret_spawners, physic_world, tempos, lazy_update, insertables_map, audio, ai_debug, mut debug_shapes): Self::SystemData,
 ){
         for (turret_spawner, activators, body) in (&mut turret_spawners, &activatorses, &bodies).join()
         {
@@ -30,23 +35,31 @@ impl<'a> System<'a> for TurretSpawnerSystem {
                     let ref rotation_activator = activators[id];
                     if rotation_activator.activated {
                         let mut position = body.get(&physic_world).position();
-                        audio.play(rotation_activator.sound, position.translation.vector.into());
+                        audio.play_at(&rotation_activator.sound, position.translation.vector.into(), rotation_activator.activated_time, &physic_world);
                         turret_part.clockwise = !turret_part.clockwise;
                         // TODO: modulo ...
                         turret_part.start_time = -(turret_part.start_time+tempo.beat as isize * 2);
                     }
                 }
 
-                if activator.activated {
-                    let mut position = body.get(&physic_world).position();
-                    audio.play(activator.sound, position.translation.vector.into());
+                let mut angle = (2.0 * PI / turret_part.rotation_time as f32)
+                    * (turret_part.start_time + tempo.beat as isize) as f32;
 
-                    let mut angle = (2.0 * PI / turret_part.rotation_time as f32)
-                        * (turret_part.start_time + tempo.beat as isize) as f32;
+                if turret_part.clockwise {
+                    angle *= -1.0;
+                }
 
-                    if turret_part.clockwise {
-                        angle *= -1.0;
-                    }
+                if ai_debug.turret_spawner {
+                    let mut position = body.get(&physic_world).position();
+                    position.rotation *= ::na::UnitComplex::new(angle);
+                    let direction = position.rotation * ::na::Vector2::new(1.0, 0.0);
+                    let start = position.translation.vector + direction * turret_part.shoot_distance;
+                    debug_shapes.push_segment(start, start + direction * DEBUG_FIRING_RAY_LENGTH);
+                }
+
+                if activator.activated {
+                    let mut position = body.get(&physic_world).position();
+                    audio.play_at(&activator.sound, position.translation.vector.into(), activator.activated_time, &physic_world);
 
                     position.rotation *= ::na::UnitComplex::new(angle);
                     ::util::move_forward(&mut position, turret_part.shoot_distance);
@@ -90,7 +90,7 @@ impl<'a> System<'a> for UniqueSpawnerSystem {
                                 .get(*bodies_map.get(&object.data().body()).unwrap())
                                 .is_some()
                             {
-                                audio.play(activator.sound, pos_vector.into());
+                                audio.play_at(&activator.sound, pos_vector.into(), activator.activated_time, &physic_world);
                                 entities.delete(entity).unwrap();
                                 let spawn =
                                     insertables_map.get(&unique_spawner.spawn).unwrap().clone();
@@ -1,4 +1,4 @@
-use specs::{Join, ReadStorage, System, WriteExpect};
+use specs::{Join, ReadExpect, ReadStorage, System, WriteExpect};
 use std::f32::consts::PI;
 
 pub struct VelocityDampingsSystem;
@@ -11,9 +11,11 @@ impl<'a> System<'a> for VelocityDampingsSystem {
         ReadStorage<'a, ::component::VelocityAimDamping>,
         ReadStorage<'a, ::component::VelocityDistanceDamping>,
         WriteExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::AiDebug>,
+        WriteExpect<'a, ::resource::DebugShapes>,
     );
 
-fn run(&mut self, (players, aims, rigid_bodies, aim_dampings, distance_dampings, mut physic_world): Self::SystemData){
+fn run(&mut self, (players, aims, rigid_bodies, aim_dampings, distance_dampings, mut physic_world, ai_debug, mut debug_shapes): Self::SystemData){
         let players_aim = (&players, &aims, &rigid_bodies)
             .join()
             .map(|(_, aim, body)| (aim.0, body.get(&physic_world).position().translation.vector))
@@ -33,6 +35,20 @@ fn run(&mut self, (players, aims, rigid_bodies, aim_dampings, distance_dampings,
                     angle_distance = 2.0 * PI - angle_distance;
                 }
                 velocity.linear *= aim_damping.compute(angle_distance);
+
+                if ai_debug.velocity_dampings {
+                    let length = v.norm();
+                    for &angle in &[aim_damping.min_t, aim_damping.max_t] {
+                        for &side in &[-1.0, 1.0] {
+                            let edge = player_aim + side * angle;
+                            let direction = ::na::Vector2::new(edge.cos(), edge.sin());
+                            debug_shapes.push_segment(
+                                *player_position,
+                                player_position + direction * length,
+                            );
+                        }
+                    }
+                }
             }
             body.set_velocity(velocity);
         }
@@ -30,8 +30,11 @@ fn run(&mut self, (players, rigid_bodies, activatorses, contactors, mut circle_t
         {
             let position = rigid_body.get(&physic_world).position().translation.vector;
             let ref activator = activators[circle_to_player.activator];
-            if !contactor.0.is_empty() || activator.activated {
-                audio.play(activator.sound, position.into());
+            if activator.activated {
+                audio.play_at(&activator.sound, position.into(), activator.activated_time, &physic_world);
+                circle_to_player.dir_shift = !circle_to_player.dir_shift;
+            } else if !contactor.0.is_empty() {
+                audio.play(&activator.sound, position.into(), &physic_world);
                 circle_to_player.dir_shift = !circle_to_player.dir_shift;
             }
 
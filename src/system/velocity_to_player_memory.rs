@@ -16,9 +16,11 @@ impl<'a> System<'a> for VelocityToPlayerMemorySystem {
         ReadExpect<'a, ::resource::BodiesMap>,
         ReadExpect<'a, ::resource::Audio>,
         WriteExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::AiDebug>,
+        WriteExpect<'a, ::resource::DebugShapes>,
     );
 
-fn run(&mut self, (players, activatorses, rigid_bodies, mut vtpms, bodies_map, audio, mut physic_world): Self::SystemData){
+fn run(&mut self, (players, activatorses, rigid_bodies, mut vtpms, bodies_map, audio, mut physic_world, ai_debug, mut debug_shapes): Self::SystemData){
         let players_position = (&players, &rigid_bodies)
             .join()
             .map(|(_, body)| body.get(&physic_world).position().translation.vector)
@@ -64,7 +66,7 @@ fn run(&mut self, (players, activatorses, rigid_bodies, mut vtpms, bodies_map, a
                     .map(|(object_position, _)| object_position);
 
                 if closest_in_sight.is_some() {
-                    audio.play(activator.sound, position.into());
+                    audio.play_at(&activator.sound, position.into(), activator.activated_time, &physic_world);
                 }
 
                 if vtpm.memory {
@@ -87,6 +89,15 @@ fn run(&mut self, (players, activatorses, rigid_bodies, mut vtpms, bodies_map, a
                 ::na::zero()
             };
 
+            if ai_debug.velocity_to_player_memory {
+                for player_position in &players_position {
+                    debug_shapes.push_line_of_sight(position, *player_position, &physic_world);
+                }
+                if let Some(last_closest_in_sight) = vtpm.last_closest_in_sight {
+                    debug_shapes.push_marker(last_closest_in_sight);
+                }
+            }
+
             rigid_body
                 .get_mut(&mut physic_world)
                 .set_velocity(Velocity {
@@ -19,9 +19,11 @@ impl<'a> System<'a> for VelocityToPlayerRandomSystem {
         ReadExpect<'a, ::resource::BodiesMap>,
         ReadExpect<'a, ::resource::Audio>,
         WriteExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::AiDebug>,
+        WriteExpect<'a, ::resource::DebugShapes>,
     );
 
-fn run(&mut self, (players, aims, rigid_bodies, activatorses, mut vtprs, bodies_map, audio, mut physic_world): Self::SystemData){
+fn run(&mut self, (players, aims, rigid_bodies, activatorses, mut vtprs, bodies_map, audio, mut physic_world, ai_debug, mut debug_shapes): Self::SystemData){
         let mut rng = thread_rng();
         let range_0_1 = Range::new(0.0, 1.0);
         let players_position = (&players, &rigid_bodies)
@@ -33,7 +35,7 @@ fn run(&mut self, (players, aims, rigid_bodies, activatorses, mut vtprs, bodies_
             let position = rigid_body.get(&physic_world).position().translation.vector;
             let ref activator = activators[vtpr.activator];
             if activator.activated {
-                audio.play(activator.sound, position.into());
+                audio.play_at(&activator.sound, position.into(), activator.activated_time, &physic_world);
                 let closest_in_sight = players_position
                     .iter()
                     .filter_map(|player_position| {
@@ -98,6 +100,12 @@ fn run(&mut self, (players, aims, rigid_bodies, activatorses, mut vtprs, bodies_
                     .unwrap_or(::na::zero());
             }
 
+            if ai_debug.velocity_to_player_random {
+                for player_position in &players_position {
+                    debug_shapes.push_line_of_sight(position, *player_position, &physic_world);
+                }
+            }
+
             rigid_body
                 .get_mut(&mut physic_world)
                 .set_velocity(Velocity {
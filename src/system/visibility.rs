@@ -0,0 +1,43 @@
+use specs::{Entities, Join, ReadExpect, ReadStorage, System, WriteExpect, WriteStorage};
+use visibility::VisibilityPolygon;
+
+pub struct VisibilitySystem;
+
+impl<'a> System<'a> for VisibilitySystem {
+    type SystemData = (
+        ReadStorage<'a, ::component::Player>,
+        ReadStorage<'a, ::component::RigidBody>,
+        WriteStorage<'a, ::component::Visible>,
+        ReadExpect<'a, ::resource::PhysicWorld>,
+        ReadExpect<'a, ::resource::Conf>,
+        WriteExpect<'a, ::resource::Visibility>,
+        Entities<'a>,
+    );
+
+    fn run(
+        &mut self,
+        (players, bodies, mut visibles, physic_world, conf, mut visibility, entities): Self::SystemData,
+    ) {
+        // TODO: Fix it when multiple bodies
+        let origin = (&players, &bodies).join().next().map(|(_, body)| {
+            body.get(&physic_world).position().translation.vector
+        });
+        visibility.0 = origin.map(|origin| {
+            VisibilityPolygon::compute(origin, conf.visibility_range, &physic_world)
+        });
+
+        for (body, entity) in (&bodies, &*entities).join() {
+            let position = body.get(&physic_world).position().translation.vector;
+            let visible = visibility
+                .0
+                .as_ref()
+                .map(|polygon| polygon.contains(position))
+                .unwrap_or(true);
+            if visible {
+                visibles.insert(entity, ::component::Visible).unwrap();
+            } else {
+                visibles.remove(entity);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use rusttype::{point, Font, PositionedGlyph, Scale};
+
+pub const FONT_PATH: &str = "data/fonts/DejaVuSans-Bold.ttf";
+
+#[derive(Clone, Copy)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy)]
+pub enum Space {
+    /// Position in pixels from the top left corner of the window, size in pixels
+    Screen,
+    /// Position and size in world units, follows the camera
+    World,
+}
+
+/// Text drawn on the next frame
+#[derive(Clone)]
+pub struct Text {
+    pub string: String,
+    pub position: ::na::Vector2<f32>,
+    pub space: Space,
+    pub size: f32,
+    pub color: [f32; 4],
+    pub outline: Option<[f32; 4]>,
+    /// Horizontal alignment on the position, the text is vertically centered on it
+    pub align: Align,
+}
+
+impl Text {
+    pub fn new(string: String, position: ::na::Vector2<f32>, space: Space, size: f32) -> Self {
+        Text {
+            string,
+            position,
+            space,
+            size,
+            color: [1.0, 1.0, 1.0, 1.0],
+            outline: None,
+            align: Align::Left,
+        }
+    }
+}
+
+/// Texts drawn on the next frame, drained by the graphics
+#[derive(Deref, DerefMut)]
+pub struct Texts(pub Vec<Text>);
+
+/// Lay out the glyphs of a line of text in pixels, the origin is positioned as aligned
+pub fn layout(
+    font: &Font<'static>,
+    string: &str,
+    size: f32,
+    origin: [f32; 2],
+    align: Align,
+) -> Vec<PositionedGlyph<'static>> {
+    let scale = Scale::uniform(size);
+    let v_metrics = font.v_metrics(scale);
+    let glyphs = font
+        .layout(string, scale, point(0.0, 0.0))
+        .collect::<Vec<_>>();
+
+    let width = glyphs
+        .last()
+        .map(|g| g.position().x + g.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.0);
+    let shift_x = match align {
+        Align::Left => 0.0,
+        Align::Center => -width / 2.0,
+        Align::Right => -width,
+    };
+    let baseline = origin[1] + (v_metrics.ascent + v_metrics.descent) / 2.0;
+
+    glyphs
+        .into_iter()
+        .map(|g| {
+            let position = g.position();
+            g.into_unpositioned().positioned(point(
+                origin[0] + shift_x + position.x,
+                baseline + position.y,
+            ))
+        })
+        .collect()
+}
@@ -52,6 +52,9 @@ pub fn reset_world(world: &mut World) {
     world.maintain();
     world.delete_all();
     world.write_storage::<::component::RigidBody>().retained();
+    world.write_resource::<::resource::Particles>().clear();
+    world.write_resource::<::resource::DeathAnimations>().clear();
+    world.write_resource::<::resource::AnimationEvents>().clear();
 
     let ground = world.create_entity().with(::component::Ground).build();
     world.add_resource(::resource::BodiesMap::new(ground));
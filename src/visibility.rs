@@ -0,0 +1,97 @@
+use ncollide2d::query::Ray;
+use ncollide2d::shape::Segment;
+use ncollide2d::world::CollisionGroups;
+use std::f32::consts::PI;
+
+/// Number of rays cast regularly around the origin so that the polygon follows the range
+const RANGE_DIVISIONS: usize = 32;
+/// Angle shift of the rays cast on both sides of wall extremities
+const EXTREMITY_SHIFT: f32 = 0.0001;
+
+/// Polygon of the area seen from the origin through walls, up to the range
+pub struct VisibilityPolygon {
+    origin: ::na::Vector2<f32>,
+    range: f32,
+    /// Triangles from the origin to consecutive points of the polygon, counter clockwise
+    triangles: Vec<[::na::Vector2<f32>; 3]>,
+}
+
+impl VisibilityPolygon {
+    pub fn compute(
+        origin: ::na::Vector2<f32>,
+        range: f32,
+        physic_world: &::resource::PhysicWorld,
+    ) -> Self {
+        let mut collision_groups = CollisionGroups::new();
+        collision_groups.set_whitelist(&[::entity::Group::Wall as usize]);
+
+        let mut angles = (0..RANGE_DIVISIONS)
+            .map(|i| i as f32 * 2.0 * PI / RANGE_DIVISIONS as f32 - PI)
+            .collect::<Vec<_>>();
+
+        for object in physic_world.collision_world().collision_objects() {
+            if !object
+                .collision_groups()
+                .is_member_of(::entity::Group::Wall as usize)
+            {
+                continue;
+            }
+            if let Some(segment) = object.shape().as_shape::<Segment<f32>>() {
+                for extremity in &[segment.a(), segment.b()] {
+                    let vector = (object.position() * *extremity).coords - origin;
+                    if vector.norm() > range {
+                        continue;
+                    }
+                    let angle = vector[1].atan2(vector[0]);
+                    angles.push(angle - EXTREMITY_SHIFT);
+                    angles.push(angle);
+                    angles.push(angle + EXTREMITY_SHIFT);
+                }
+            }
+        }
+
+        angles.sort_by_key(|angle| (angle * ::CMP_PRECISION * 1000.0) as isize);
+        angles.dedup();
+
+        let points = angles
+            .iter()
+            .map(|angle| {
+                let direction = ::na::Vector2::new(angle.cos(), angle.sin());
+                let ray = Ray::new(::na::Point::from_coordinates(origin), direction);
+                let distance = physic_world
+                    .collision_world()
+                    .interferences_with_ray(&ray, &collision_groups)
+                    .map(|(_, intersection)| intersection.toi)
+                    .fold(range, f32::min);
+                origin + direction * distance
+            })
+            .collect::<Vec<_>>();
+
+        let triangles = (0..points.len())
+            .map(|i| [origin, points[i], points[(i + 1) % points.len()]])
+            .collect();
+
+        VisibilityPolygon {
+            origin,
+            range,
+            triangles,
+        }
+    }
+
+    /// Triangles of the polygon, counter clockwise
+    pub fn triangles(&self) -> &[[::na::Vector2<f32>; 3]] {
+        &self.triangles
+    }
+
+    pub fn contains(&self, point: ::na::Vector2<f32>) -> bool {
+        if (point - self.origin).norm() > self.range {
+            return false;
+        }
+        let cross = |a: ::na::Vector2<f32>, b: ::na::Vector2<f32>| a[0] * b[1] - a[1] * b[0];
+        self.triangles.iter().any(|&[o, a, b]| {
+            cross(a - o, point - o) >= 0.0
+                && cross(b - a, point - a) >= 0.0
+                && cross(o - b, point - b) >= 0.0
+        })
+    }
+}